@@ -0,0 +1,112 @@
+//! floods rooms with chats and whispers and reports how many allocations each one cost the
+//! process, server and clients together. run with `cargo bench --bench chat_flood`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rusty_robots::config::ServerConfig;
+use serde_json::json;
+
+use common::*;
+
+const ROOMS: usize = 20;
+const PLAYERS: usize = 8;
+// one under the chat burst, so nothing gets rate limited
+const MESSAGES_PER_PLAYER: usize = 4;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// sends every player's messages in turn, waiting for each to reach everyone it's meant for.
+async fn flood(sockets: &mut [Socket]) -> usize {
+    let mut sent = 0;
+    for round in 0..MESSAGES_PER_PLAYER {
+        for from in 0..PLAYERS {
+            if round % 2 == 0 {
+                say(
+                    &mut sockets[from],
+                    json!({ "type": "chat", "text": "hi all" }),
+                )
+                .await;
+                for socket in sockets.iter_mut() {
+                    expect(socket, "chat").await;
+                }
+            } else {
+                let to = (from + 1) % PLAYERS;
+                let whisper =
+                    json!({ "type": "whisper", "to": format!("player{to}"), "text": "psst" });
+                say(&mut sockets[from], whisper).await;
+                expect(&mut sockets[from], "whisper").await;
+                expect(&mut sockets[to], "whisper").await;
+            }
+            sent += 1;
+        }
+    }
+    sent
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let (app, addr) = serve_with(ServerConfig {
+        max_connections_per_ip: ROOMS * PLAYERS,
+        ..ServerConfig::default()
+    })
+    .await;
+
+    let mut allocations = 0;
+    let mut sent = 0;
+    let mut rooms = Vec::new();
+    for _ in 0..ROOMS {
+        let created = post_ok(&app, "/rooms/create", json!({ "username": "player0" })).await;
+        let code = created["code"].clone();
+        let mut tokens = vec![created["token"].clone()];
+        for player in 1..PLAYERS {
+            let joined = post_ok(
+                &app,
+                &format!("/rooms/{}/join", code.as_str().unwrap()),
+                json!({ "username": format!("player{player}") }),
+            )
+            .await;
+            tokens.push(joined["token"].clone());
+        }
+        let mut sockets = Vec::new();
+        for token in &tokens {
+            sockets.push(connect(addr, &code, token).await);
+        }
+        // let the joins settle before counting
+        for socket in &mut sockets {
+            say(socket, json!({ "type": "ping", "nonce": 0 })).await;
+            expect(socket, "pong").await;
+        }
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        sent += flood(&mut sockets).await;
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - before;
+        rooms.push(sockets);
+    }
+
+    println!(
+        "{sent} messages in {ROOMS} rooms of {PLAYERS}: {allocations} allocations, {:.1} per message",
+        allocations as f64 / sent as f64
+    );
+}
@@ -0,0 +1,261 @@
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use axum::http::{HeaderValue, Method};
+use thiserror::Error;
+
+const HOST_VAR: &str = "RUSTY_ROBOTS_HOST";
+const PORT_VAR: &str = "RUSTY_ROBOTS_PORT";
+const CODE_LEN_VAR: &str = "RUSTY_ROBOTS_CODE_LEN";
+const TOKEN_LEN_VAR: &str = "RUSTY_ROBOTS_TOKEN_LEN";
+const CHANNEL_CAPACITY_VAR: &str = "RUSTY_ROBOTS_CHANNEL_CAPACITY";
+const SHUTDOWN_GRACE_SECONDS_VAR: &str = "RUSTY_ROBOTS_SHUTDOWN_GRACE_SECONDS";
+const PERSIST_PATH_VAR: &str = "RUSTY_ROBOTS_PERSIST_PATH";
+const PERSIST_INTERVAL_SECONDS_VAR: &str = "RUSTY_ROBOTS_PERSIST_INTERVAL_SECONDS";
+const CORS_ORIGINS_VAR: &str = "RUSTY_ROBOTS_CORS_ORIGINS";
+const CORS_METHODS_VAR: &str = "RUSTY_ROBOTS_CORS_METHODS";
+const CORS_CREDENTIALS_VAR: &str = "RUSTY_ROBOTS_CORS_CREDENTIALS";
+const SECURE_COOKIES_VAR: &str = "RUSTY_ROBOTS_SECURE_COOKIES";
+const EVICT_CONNECTIONS_VAR: &str = "RUSTY_ROBOTS_EVICT_CONNECTIONS";
+const COMPRESSION_VAR: &str = "RUSTY_ROBOTS_COMPRESSION";
+const ADMIN_TOKEN_VAR: &str = "RUSTY_ROBOTS_ADMIN_TOKEN";
+const WORD_LIST_VAR: &str = "RUSTY_ROBOTS_WORD_LIST";
+const MAX_CONNECTIONS_PER_IP_VAR: &str = "RUSTY_ROBOTS_MAX_CONNECTIONS_PER_IP";
+const RESULTS_TTL_SECONDS_VAR: &str = "RUSTY_ROBOTS_RESULTS_TTL_SECONDS";
+const MAX_ROOMS_VAR: &str = "RUSTY_ROBOTS_MAX_ROOMS";
+const HOST_CONNECT_TIMEOUT_SECONDS_VAR: &str = "RUSTY_ROBOTS_HOST_CONNECT_TIMEOUT_SECONDS";
+const BUSY_TIMEOUT_MILLIS_VAR: &str = "RUSTY_ROBOTS_BUSY_TIMEOUT_MILLIS";
+const MAX_MESSAGE_BYTES_VAR: &str = "RUSTY_ROBOTS_MAX_MESSAGE_BYTES";
+const TLS_CERT_VAR: &str = "RUSTY_ROBOTS_TLS_CERT";
+const TLS_KEY_VAR: &str = "RUSTY_ROBOTS_TLS_KEY";
+
+const DEFAULT_HOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+const DEFAULT_PORT: u16 = 3003;
+const DEFAULT_CODE_LEN: usize = 4;
+const DEFAULT_TOKEN_LEN: usize = 16;
+const DEFAULT_CHANNEL_CAPACITY: usize = 10;
+// a connecting player is sent a welcome, the chat history and a couple of updates before anything
+// drains their channel, so anything smaller can leave the room stuck waiting on them
+const MIN_CHANNEL_CAPACITY: usize = 8;
+const DEFAULT_SHUTDOWN_GRACE_SECONDS: u64 = 5;
+const DEFAULT_PERSIST_INTERVAL_SECONDS: u64 = 30;
+const DEFAULT_MAX_CONNECTIONS_PER_IP: usize = 20;
+const DEFAULT_RESULTS_TTL_SECONDS: u64 = 60 * 60;
+const DEFAULT_MAX_ROOMS: usize = 1000;
+const DEFAULT_HOST_CONNECT_TIMEOUT_SECONDS: u64 = 60;
+const DEFAULT_BUSY_TIMEOUT_MILLIS: u64 = 5000;
+const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024;
+const DEFAULT_CORS_METHODS: [Method; 2] = [Method::GET, Method::POST];
+
+#[derive(Error, Debug, Clone)]
+pub enum ConfigError {
+    #[error("invalid value '{value}' for {var}")]
+    InvalidValue { var: &'static str, value: String },
+    #[error("{0} and {1} must be set together")]
+    Unpaired(&'static str, &'static str),
+    #[error("{var} must be at least {min}")]
+    TooSmall { var: &'static str, min: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    pub code_len: usize,
+    pub token_len: usize,
+    /// how many messages can wait to be sent to one player. at least 8.
+    pub channel_capacity: usize,
+    pub shutdown_grace_seconds: u64,
+    /// where rooms are saved to and restored from, if anywhere.
+    pub persist_path: Option<PathBuf>,
+    pub persist_interval_seconds: u64,
+    /// origins allowed to make cross-origin requests. only used without the bundled client, and
+    /// empty by default so no other origin is let in.
+    pub cors_origins: Vec<HeaderValue>,
+    pub cors_methods: Vec<Method>,
+    /// whether cross-origin requests may carry the token cookie.
+    pub cors_credentials: bool,
+    /// marks the token cookie `Secure`. turn off to serve over plain http, e.g. in development.
+    pub secure_cookies: bool,
+    /// lets a player's new websocket take over from their old one, e.g. a forgotten tab, instead
+    /// of being turned away.
+    pub evict_connections: bool,
+    /// lets websocket clients ask for large messages to be deflated with `?compress=deflate`.
+    pub compression: bool,
+    /// the bearer token the `/admin` routes ask for. they're turned off when unset.
+    pub admin_token: Option<String>,
+    /// a file of words to keep out of usernames and chat, one per line.
+    pub word_list: Option<PathBuf>,
+    /// how many websockets, as player or spectator, one address can hold open at once.
+    pub max_connections_per_ip: usize,
+    /// how long a finished game's room is kept, so its results can still be fetched.
+    pub results_ttl_seconds: u64,
+    /// how many rooms can exist at once. creating more is refused until some are cleared out.
+    pub max_rooms: usize,
+    /// how long a new room waits for its host to connect before it's dropped.
+    pub host_connect_timeout_seconds: u64,
+    /// how long a request waits on a room that's tied up, e.g. sending to a stalled connection,
+    /// before it's answered with `503 room_busy`.
+    pub busy_timeout_millis: u64,
+    /// the largest message a client can send over its websocket. anything bigger closes the
+    /// connection.
+    pub max_message_bytes: usize,
+    /// a pem certificate chain and private key to serve https with. plain http when unset.
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::new(DEFAULT_HOST, DEFAULT_PORT),
+            code_len: DEFAULT_CODE_LEN,
+            token_len: DEFAULT_TOKEN_LEN,
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            shutdown_grace_seconds: DEFAULT_SHUTDOWN_GRACE_SECONDS,
+            persist_path: None,
+            persist_interval_seconds: DEFAULT_PERSIST_INTERVAL_SECONDS,
+            cors_origins: Vec::new(),
+            cors_methods: DEFAULT_CORS_METHODS.to_vec(),
+            cors_credentials: false,
+            secure_cookies: true,
+            evict_connections: false,
+            compression: true,
+            admin_token: None,
+            word_list: None,
+            max_connections_per_ip: DEFAULT_MAX_CONNECTIONS_PER_IP,
+            results_ttl_seconds: DEFAULT_RESULTS_TTL_SECONDS,
+            max_rooms: DEFAULT_MAX_ROOMS,
+            host_connect_timeout_seconds: DEFAULT_HOST_CONNECT_TIMEOUT_SECONDS,
+            busy_timeout_millis: DEFAULT_BUSY_TIMEOUT_MILLIS,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            tls: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(|var| env::var(var).ok())
+    }
+
+    /// builds a config from an arbitrary variable lookup, falling back to the defaults for any
+    /// variable that isn't set.
+    pub fn from_vars<F>(lookup: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let default = Self::default();
+
+        Ok(Self {
+            addr: SocketAddr::new(
+                parse_var(&lookup, HOST_VAR)?.unwrap_or(default.addr.ip()),
+                parse_var(&lookup, PORT_VAR)?.unwrap_or(default.addr.port()),
+            ),
+            code_len: parse_var::<NonZeroUsize, _>(&lookup, CODE_LEN_VAR)?
+                .map_or(default.code_len, NonZeroUsize::get),
+            token_len: parse_var::<NonZeroUsize, _>(&lookup, TOKEN_LEN_VAR)?
+                .map_or(default.token_len, NonZeroUsize::get),
+            channel_capacity: match parse_var(&lookup, CHANNEL_CAPACITY_VAR)? {
+                Some(capacity) if capacity < MIN_CHANNEL_CAPACITY => {
+                    return Err(ConfigError::TooSmall {
+                        var: CHANNEL_CAPACITY_VAR,
+                        min: MIN_CHANNEL_CAPACITY,
+                    });
+                }
+                capacity => capacity.unwrap_or(default.channel_capacity),
+            },
+            shutdown_grace_seconds: parse_var(&lookup, SHUTDOWN_GRACE_SECONDS_VAR)?
+                .unwrap_or(default.shutdown_grace_seconds),
+            persist_path: parse_var(&lookup, PERSIST_PATH_VAR)?,
+            persist_interval_seconds: parse_var::<NonZeroU64, _>(
+                &lookup,
+                PERSIST_INTERVAL_SECONDS_VAR,
+            )?
+            .map_or(default.persist_interval_seconds, NonZeroU64::get),
+            cors_origins: parse_list(&lookup, CORS_ORIGINS_VAR)?.unwrap_or(default.cors_origins),
+            cors_methods: parse_list(&lookup, CORS_METHODS_VAR)?.unwrap_or(default.cors_methods),
+            cors_credentials: parse_var(&lookup, CORS_CREDENTIALS_VAR)?
+                .unwrap_or(default.cors_credentials),
+            secure_cookies: parse_var(&lookup, SECURE_COOKIES_VAR)?
+                .unwrap_or(default.secure_cookies),
+            evict_connections: parse_var(&lookup, EVICT_CONNECTIONS_VAR)?
+                .unwrap_or(default.evict_connections),
+            compression: parse_var(&lookup, COMPRESSION_VAR)?.unwrap_or(default.compression),
+            admin_token: parse_var(&lookup, ADMIN_TOKEN_VAR)?,
+            word_list: parse_var(&lookup, WORD_LIST_VAR)?,
+            max_connections_per_ip: parse_var::<NonZeroUsize, _>(
+                &lookup,
+                MAX_CONNECTIONS_PER_IP_VAR,
+            )?
+            .map_or(default.max_connections_per_ip, NonZeroUsize::get),
+            results_ttl_seconds: parse_var::<NonZeroU64, _>(&lookup, RESULTS_TTL_SECONDS_VAR)?
+                .map_or(default.results_ttl_seconds, NonZeroU64::get),
+            max_rooms: parse_var::<NonZeroUsize, _>(&lookup, MAX_ROOMS_VAR)?
+                .map_or(default.max_rooms, NonZeroUsize::get),
+            host_connect_timeout_seconds: parse_var::<NonZeroU64, _>(
+                &lookup,
+                HOST_CONNECT_TIMEOUT_SECONDS_VAR,
+            )?
+            .map_or(default.host_connect_timeout_seconds, NonZeroU64::get),
+            busy_timeout_millis: parse_var::<NonZeroU64, _>(&lookup, BUSY_TIMEOUT_MILLIS_VAR)?
+                .map_or(default.busy_timeout_millis, NonZeroU64::get),
+            max_message_bytes: parse_var::<NonZeroUsize, _>(&lookup, MAX_MESSAGE_BYTES_VAR)?
+                .map_or(default.max_message_bytes, NonZeroUsize::get),
+            tls: match (
+                parse_var(&lookup, TLS_CERT_VAR)?,
+                parse_var(&lookup, TLS_KEY_VAR)?,
+            ) {
+                (Some(cert), Some(key)) => Some(TlsConfig { cert, key }),
+                (None, None) => None,
+                _ => return Err(ConfigError::Unpaired(TLS_CERT_VAR, TLS_KEY_VAR)),
+            },
+        })
+    }
+}
+
+fn parse_var<T, F>(lookup: &F, var: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    F: Fn(&str) -> Option<String>,
+{
+    lookup(var)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ConfigError::InvalidValue { var, value })
+        })
+        .transpose()
+}
+
+/// parses a comma-separated variable, ignoring blank entries.
+fn parse_list<T, F>(lookup: &F, var: &'static str) -> Result<Option<Vec<T>>, ConfigError>
+where
+    T: FromStr,
+    F: Fn(&str) -> Option<String>,
+{
+    lookup(var)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| {
+                    item.parse().map_err(|_| ConfigError::InvalidValue {
+                        var,
+                        value: value.clone(),
+                    })
+                })
+                .collect()
+        })
+        .transpose()
+}
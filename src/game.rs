@@ -0,0 +1,481 @@
+use std::collections::HashSet;
+
+use rand::{Rng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+
+pub const BOARD_SIZE: u8 = 16;
+// the sizes `Board::generate` can lay out, smallest first
+pub const BOARD_SIZES: [u8; 3] = [8, 12, 16];
+// how many moves deep `Board::solve` looks before giving up
+pub const MAX_SEARCH_DEPTH: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Robot {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+}
+
+impl Robot {
+    pub const ALL: [Self; 4] = [Self::Red, Self::Green, Self::Blue, Self::Yellow];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Self; 4] = [Self::Up, Self::Down, Self::Left, Self::Right];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    fn opposite(self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+
+    fn offset(self) -> (i8, i8) {
+        match self {
+            Self::Up => (0, -1),
+            Self::Down => (0, 1),
+            Self::Left => (-1, 0),
+            Self::Right => (1, 0),
+        }
+    }
+}
+
+/// a cell on the board, with `x` growing rightwards and `y` growing downwards.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Position {
+    pub x: u8,
+    pub y: u8,
+}
+
+impl Position {
+    pub fn new(x: u8, y: u8) -> Self {
+        Self { x, y }
+    }
+}
+
+/// which robots win a round by reaching a target. sent as the target's `robot`, or `null` for a
+/// wildcard.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(from = "Option<Robot>", into = "Option<Robot>")]
+pub enum TargetKind {
+    /// only the robot of the target's color.
+    Robot(Robot),
+    /// whichever robot gets there.
+    Wildcard,
+}
+
+impl TargetKind {
+    pub fn accepts(self, robot: Robot) -> bool {
+        match self {
+            Self::Robot(wanted) => wanted == robot,
+            Self::Wildcard => true,
+        }
+    }
+}
+
+impl From<Option<Robot>> for TargetKind {
+    fn from(robot: Option<Robot>) -> Self {
+        robot.map_or(Self::Wildcard, Self::Robot)
+    }
+}
+
+impl From<TargetKind> for Option<Robot> {
+    fn from(kind: TargetKind) -> Self {
+        match kind {
+            TargetKind::Robot(robot) => Some(robot),
+            TargetKind::Wildcard => None,
+        }
+    }
+}
+
+/// a cell that a robot has to be moved onto to win a round.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Target {
+    #[serde(rename = "robot")]
+    pub kind: TargetKind,
+    pub position: Position,
+}
+
+impl Target {
+    /// whether a robot the target accepts is on it.
+    fn is_reached(&self, robots: &[Position; 4]) -> bool {
+        Robot::ALL
+            .into_iter()
+            .any(|robot| self.kind.accepts(robot) && robots[robot.index()] == self.position)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    size: u8,
+    // one bitmask of `Direction` bits per cell, row by row
+    walls: Vec<u8>,
+    // indexed in `Robot::ALL` order
+    robots: [Position; 4],
+    targets: Vec<Target>,
+}
+
+impl Board {
+    /// an open board with no walls or targets, and the robots lined up along the top edge.
+    pub fn new(size: u8) -> Self {
+        assert!(size >= 4, "board too small for every robot");
+        Self {
+            size,
+            walls: vec![0; size as usize * size as usize],
+            robots: Robot::ALL.map(|robot| Position::new(robot as u8, 0)),
+            targets: Vec::new(),
+        }
+    }
+
+    /// lays out a random board of one of the `BOARD_SIZES`: a walled-off centre, a few walls
+    /// jutting in from each edge, and an L-shaped corner around every target. full size boards
+    /// also get a wildcard target, in a corner of its own. the layout only
+    /// depends on `size` and what `rng` yields, so a seeded rng always produces the same board.
+    pub fn generate<R: Rng>(size: u8, rng: &mut R) -> Self {
+        assert!(
+            BOARD_SIZES.contains(&size),
+            "no layout for a board of {size}"
+        );
+        // on small boards the edge walls and corners can seal a target into a pocket, so lay out
+        // boards until none is
+        loop {
+            let board = Self::lay_out(size, rng);
+            if board.targets.iter().all(|target| {
+                Robot::ALL
+                    .into_iter()
+                    .filter(|&robot| target.kind.accepts(robot))
+                    .any(|robot| board.is_reachable(robot, target.position))
+            }) {
+                return board;
+            }
+        }
+    }
+
+    fn lay_out<R: Rng>(size: u8, rng: &mut R) -> Self {
+        let mut board = Self::new(size);
+        let half = size / 2;
+
+        for (x, y) in [(half - 1, half - 1), (half, half - 1)] {
+            board.add_wall(Position::new(x, y), Direction::Up);
+            board.add_wall(Position::new(x, y + 1), Direction::Down);
+        }
+        for y in [half - 1, half] {
+            board.add_wall(Position::new(half - 1, y), Direction::Left);
+            board.add_wall(Position::new(half, y), Direction::Right);
+        }
+
+        // one wall per edge per half, kept away from the corners
+        for offset in [0, half] {
+            let x = offset + rng.random_range(2..half - 1);
+            board.add_wall(Position::new(x, 0), Direction::Right);
+            let x = offset + rng.random_range(2..half - 1);
+            board.add_wall(Position::new(x, size - 1), Direction::Right);
+            let y = offset + rng.random_range(2..half - 1);
+            board.add_wall(Position::new(0, y), Direction::Down);
+            let y = offset + rng.random_range(2..half - 1);
+            board.add_wall(Position::new(size - 1, y), Direction::Down);
+        }
+
+        let per_quadrant = size as usize / 4;
+        let mut robots = Robot::ALL.repeat(per_quadrant);
+        robots.shuffle(rng);
+
+        let quadrants = [(0, 0), (half, 0), (0, half), (half, half)];
+        // keep corners from touching, so they don't wall each other in
+        let touches = |corners: &[Position], position: Position| {
+            corners
+                .iter()
+                .any(|corner| corner.x.abs_diff(position.x) + corner.y.abs_diff(position.y) < 2)
+        };
+        let mut corners = Vec::with_capacity(robots.len());
+        for (qx, qy) in quadrants {
+            let mut placed = 0;
+            while placed < per_quadrant {
+                let position = Position::new(
+                    qx + rng.random_range(1..half - 1),
+                    qy + rng.random_range(1..half - 1),
+                );
+                if touches(&corners, position) {
+                    continue;
+                }
+                board.add_corner(position, rng);
+                corners.push(position);
+                placed += 1;
+            }
+        }
+
+        board.targets = robots
+            .into_iter()
+            .zip(corners.iter().copied())
+            .map(|(robot, position)| Target {
+                kind: TargetKind::Robot(robot),
+                position,
+            })
+            .collect();
+
+        let mut placed = Vec::with_capacity(Robot::ALL.len());
+        while placed.len() < Robot::ALL.len() {
+            let position = Position::new(rng.random_range(..size), rng.random_range(..size));
+            if !board.is_centre(position)
+                && !placed.contains(&position)
+                && !board
+                    .targets
+                    .iter()
+                    .any(|target| target.position == position)
+            {
+                placed.push(position);
+            }
+        }
+        for (robot, position) in Robot::ALL.into_iter().zip(placed) {
+            board.place_robot(robot, position);
+        }
+
+        // laid out last, so it doesn't move anything else from where the seed put it
+        if size == BOARD_SIZE {
+            loop {
+                let (qx, qy) = quadrants[rng.random_range(..quadrants.len())];
+                let position = Position::new(
+                    qx + rng.random_range(1..half - 1),
+                    qy + rng.random_range(1..half - 1),
+                );
+                if touches(&corners, position) || board.robots.contains(&position) {
+                    continue;
+                }
+                board.add_corner(position, rng);
+                board.targets.push(Target {
+                    kind: TargetKind::Wildcard,
+                    position,
+                });
+                break;
+            }
+        }
+
+        board
+    }
+
+    /// walls in `position` on one side and the top or bottom, for a target to sit in.
+    fn add_corner<R: Rng>(&mut self, position: Position, rng: &mut R) {
+        let vertical = if rng.random() {
+            Direction::Up
+        } else {
+            Direction::Down
+        };
+        let horizontal = if rng.random() {
+            Direction::Left
+        } else {
+            Direction::Right
+        };
+        self.add_wall(position, vertical);
+        self.add_wall(position, horizontal);
+    }
+
+    pub fn size(&self) -> u8 {
+        self.size
+    }
+
+    pub fn targets(&self) -> &[Target] {
+        &self.targets
+    }
+
+    pub fn robot(&self, robot: Robot) -> Position {
+        self.robots[robot.index()]
+    }
+
+    /// every robot's position, in `Robot::ALL` order.
+    pub fn robots(&self) -> [Position; 4] {
+        self.robots
+    }
+
+    pub fn place_robot(&mut self, robot: Robot, position: Position) {
+        self.robots[robot.index()] = position;
+    }
+
+    pub fn place_robots(&mut self, positions: [Position; 4]) {
+        self.robots = positions;
+    }
+
+    pub fn add_target(&mut self, target: Target) {
+        self.targets.push(target);
+    }
+
+    /// puts a wall on the `direction` side of `position`, and on the facing side of its
+    /// neighbour so the wall blocks both ways.
+    pub fn add_wall(&mut self, position: Position, direction: Direction) {
+        let index = self.index(position);
+        self.walls[index] |= direction.bit();
+
+        if let Some(neighbour) = self.neighbour(position, direction) {
+            let index = self.index(neighbour);
+            self.walls[index] |= direction.opposite().bit();
+        }
+    }
+
+    pub fn has_wall(&self, position: Position, direction: Direction) -> bool {
+        self.walls[self.index(position)] & direction.bit() != 0
+    }
+
+    /// where `robot` would stop if it moved in `direction`: the last cell before a wall, the
+    /// edge of the board, or another robot.
+    pub fn slide(&self, robot: Robot, direction: Direction) -> Position {
+        self.slide_among(&self.robots, robot, direction)
+    }
+
+    /// the fewest moves that get a robot the target accepts onto it, or `None` if it takes more
+    /// than `MAX_SEARCH_DEPTH`.
+    pub fn min_moves(&self, target: Target) -> Option<u32> {
+        self.solve(target).map(|path| path.len() as u32)
+    }
+
+    /// one of the shortest sequences of moves that gets a robot the target accepts onto it, found
+    /// with a breadth first search over where the robots could be.
+    pub fn solve(&self, target: Target) -> Option<Vec<(Robot, Direction)>> {
+        if target.is_reached(&self.robots) {
+            return Some(Vec::new());
+        }
+
+        // every arrangement reached so far, with the move that reached it from its parent. the
+        // root's move is never followed
+        let mut nodes = vec![(self.robots, 0, (Robot::Red, Direction::Up))];
+        let mut seen = HashSet::from([canonical(self.robots, target.kind)]);
+        let mut level = 0..1;
+
+        for _ in 0..MAX_SEARCH_DEPTH {
+            let end = nodes.len();
+            for parent in level {
+                let robots = nodes[parent].0;
+                for robot in Robot::ALL {
+                    for direction in Direction::ALL {
+                        let to = self.slide_among(&robots, robot, direction);
+                        if to == robots[robot.index()] {
+                            continue;
+                        }
+                        let mut next = robots;
+                        next[robot.index()] = to;
+                        if !seen.insert(canonical(next, target.kind)) {
+                            continue;
+                        }
+
+                        nodes.push((next, parent, (robot, direction)));
+                        if target.is_reached(&next) {
+                            return Some(path(&nodes));
+                        }
+                    }
+                }
+            }
+            level = end..nodes.len();
+        }
+        None
+    }
+
+    /// whether `robot` could get onto `target` by itself from where it starts, with the other
+    /// robots parked in the centre out of its way.
+    fn is_reachable(&self, robot: Robot, target: Position) -> bool {
+        let half = self.size / 2;
+        let mut robots = [
+            Position::new(half - 1, half - 1),
+            Position::new(half, half - 1),
+            Position::new(half - 1, half),
+            Position::new(half, half),
+        ];
+        let start = self.robot(robot);
+        let mut seen = HashSet::from([start]);
+        let mut frontier = vec![start];
+        while let Some(position) = frontier.pop() {
+            if position == target {
+                return true;
+            }
+            robots[robot.index()] = position;
+            for direction in Direction::ALL {
+                let to = self.slide_among(&robots, robot, direction);
+                if seen.insert(to) {
+                    frontier.push(to);
+                }
+            }
+        }
+        false
+    }
+
+    fn slide_among(&self, robots: &[Position; 4], robot: Robot, direction: Direction) -> Position {
+        let mut position = robots[robot.index()];
+        while let Some(next) = self.step(position, direction) {
+            if robots.contains(&next) {
+                break;
+            }
+            position = next;
+        }
+        position
+    }
+
+    fn step(&self, position: Position, direction: Direction) -> Option<Position> {
+        if self.has_wall(position, direction) {
+            None
+        } else {
+            self.neighbour(position, direction)
+        }
+    }
+
+    fn neighbour(&self, position: Position, direction: Direction) -> Option<Position> {
+        let (dx, dy) = direction.offset();
+        let x = position.x.checked_add_signed(dx)?;
+        let y = position.y.checked_add_signed(dy)?;
+        (x < self.size && y < self.size).then_some(Position::new(x, y))
+    }
+
+    fn is_centre(&self, position: Position) -> bool {
+        let half = self.size / 2;
+        (half - 1..=half).contains(&position.x) && (half - 1..=half).contains(&position.y)
+    }
+
+    fn index(&self, position: Position) -> usize {
+        position.y as usize * self.size as usize + position.x as usize
+    }
+}
+
+// only the target's robot has to reach anywhere, so the others are interchangeable and
+// arrangements that just swap them around needn't be searched twice. any robot can reach a
+// wildcard, which makes them all interchangeable
+fn canonical(robots: [Position; 4], target: TargetKind) -> [Position; 4] {
+    let mut key = robots;
+    match target {
+        TargetKind::Robot(robot) => {
+            key.swap(0, robot.index());
+            key[1..].sort();
+        }
+        TargetKind::Wildcard => key.sort(),
+    }
+    key
+}
+
+/// the moves leading to the last node, from the root.
+fn path(nodes: &[([Position; 4], usize, (Robot, Direction))]) -> Vec<(Robot, Direction)> {
+    let mut path = Vec::new();
+    let mut index = nodes.len() - 1;
+    while index != 0 {
+        let (_, parent, step) = nodes[index];
+        path.push(step);
+        index = parent;
+    }
+    path.reverse();
+    path
+}
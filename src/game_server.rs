@@ -1,264 +1,1695 @@
 use axum::{
     Json, Router,
     extract::{
-        Path, State, WebSocketUpgrade,
-        ws::{Message, WebSocket},
+        ConnectInfo, Extension, FromRequestParts, Path, Query, State, WebSocketUpgrade,
+        ws::{CloseFrame, Message, WebSocket, close_code},
+    },
+    http::{
+        HeaderMap, StatusCode,
+        header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL},
+        request::Parts,
     },
-    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     routing::{get, post},
 };
-use axum_extra::extract::CookieJar;
-use base64::{Engine, engine::general_purpose::STANDARD};
+use axum_extra::extract::{
+    CookieJar,
+    cookie::{Cookie, SameSite},
+};
 use futures_util::{SinkExt, stream::StreamExt};
 use rand::{Rng, rng};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use subtle::ConstantTimeEq;
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::{
+    sync::{
+        Mutex, MutexGuard,
+        broadcast::{self, error::RecvError},
+        oneshot,
+    },
+    time::{Instant, timeout},
+};
+use tracing::{Instrument, Span};
 
-use room::{PlayerMessage, Room, RoomError};
+use crate::config::ServerConfig;
+use room::{
+    CloseReason, Connection, Envelope, PROTOCOL_VERSION, Room, RoomError, RoomOptions,
+    RoomSettings, RoomSummary, Watching,
+};
+use token::Token;
+use validation::{Password, RoomCode, Username, ValidationError, WordFilter};
+use websocket::{Compression, Encoding, Format};
 
+pub use room::{Emote, PlayerMessage, ServerMessage};
+
+mod bot;
+mod persistence;
 mod room;
+mod token;
+mod validation;
 mod websocket;
 
-const NUM_CODE_CHARS: usize = 36;
+// excludes 'I', 'O', '0', and '1' so codes can't be mistyped
+const NUM_CODE_CHARS: usize = 32;
 const CODE_CHARS: [char; NUM_CODE_CHARS] = [
-    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S',
-    'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'L', 'M', 'N', 'P', 'Q', 'R', 'S', 'T', 'U',
+    'V', 'W', 'X', 'Y', 'Z', '2', '3', '4', '5', '6', '7', '8', '9',
 ];
-const CODE_LEN: usize = 4;
 
-type ServerState = Arc<Mutex<HashMap<Arc<str>, Arc<Mutex<Room>>>>>;
+const CLOSE_ALREADY_CONNECTED: u16 = 4001;
+const CLOSE_REPLACED: u16 = 4002;
+const CLOSE_LEFT: u16 = 4003;
+const CLOSE_KICKED: u16 = 4004;
+const CLOSE_ROOM_CLOSED: u16 = 4005;
+
+const BEARER_PROTOCOL: &str = "bearer";
+
+const TICK_PERIOD: Duration = Duration::from_secs(1);
+// the longest between checks for rooms that have outlived `results_ttl` or `host_connect_timeout`
+const SWEEP_PERIOD: Duration = Duration::from_secs(60);
+// how long a retried create with the same `Idempotency-Key` gets the original room back
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(10 * 60);
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+// a locked room's password, for spectating it. kept out of the url so it isn't logged with it
+const PASSWORD_HEADER: &str = "room-password";
+
+#[derive(Debug)]
+struct GameServer {
+    rooms: Mutex<HashMap<Arc<str>, Arc<Mutex<Room>>>>,
+    code_len: usize,
+    room_options: RoomOptions,
+    persist_path: Option<PathBuf>,
+    secure_cookies: bool,
+    evict_connections: bool,
+    compression: bool,
+    admin_token: Option<Token>,
+    // recent creates by client and idempotency key. this does hold live host tokens, but only until
+    // the key expires, and only ever hands them back to the client that asked
+    created: Mutex<HashMap<CreateKey, Created>>,
+    max_connections_per_ip: usize,
+    results_ttl: Duration,
+    // how long a new room waits for its host to connect before it's dropped
+    host_connect_timeout: Duration,
+    max_rooms: usize,
+    // how long a request waits on a room someone else has locked before giving up
+    busy_timeout: Duration,
+    // the largest frame or message a client can send before its websocket is closed
+    max_message_bytes: usize,
+    // open websockets per client address, players and spectators alike
+    connections_per_ip: std::sync::Mutex<HashMap<IpAddr, usize>>,
+    messages_sent: AtomicUsize,
+    started: Instant,
+}
+
+/// one of an address's websocket connections, given back when dropped.
+struct ConnectionSlot {
+    state: ServerState,
+    ip: IpAddr,
+}
+
+impl ConnectionSlot {
+    fn claim(state: &ServerState, ip: IpAddr) -> Result<Self, ServerError> {
+        let mut connections = state.connections_per_ip.lock().unwrap();
+        let count = connections.entry(ip).or_default();
+        if *count >= state.max_connections_per_ip {
+            tracing::warn!("refusing websocket from {ip}, which has {count} open");
+            return Err(ServerError::TooManyConnections);
+        }
+        *count += 1;
+
+        Ok(Self {
+            state: state.clone(),
+            ip,
+        })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut connections = self.state.connections_per_ip.lock().unwrap();
+        if let Some(count) = connections.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                connections.remove(&self.ip);
+            }
+        }
+    }
+}
+
+type ServerState = Arc<GameServer>;
 
-#[derive(Error, Debug, Serialize, Clone)]
+/// an idempotency key, along with the address of the client that sent it.
+type CreateKey = (Option<IpAddr>, Box<str>);
+
+/// a room made by a create with an idempotency key, for replaying to retries of the same request.
+#[derive(Debug)]
+struct Created {
+    at: Instant,
+    // a hash of the request, so the key can't be reused for a different one
+    request: [u8; 32],
+    response: CreateResponse,
+}
+
+#[derive(Error, Debug, Clone)]
 enum ServerError {
     #[error("room not found")]
     RoomNotFound,
-    #[error("username missing")]
-    MissingUsername,
-    #[error("username invalid")]
-    InvalidUsername,
-    #[error("password invalid")]
-    InvalidPassword,
+    #[error("room code invalid: {0}")]
+    InvalidCode(ValidationError),
+    #[error("username invalid: {0}")]
+    InvalidUsername(ValidationError),
+    #[error("password invalid: {0}")]
+    InvalidPassword(ValidationError),
     #[error("token missing")]
     MissingToken,
     #[error("token invalid")]
     InvalidToken,
-    #[error("room error: {0}")]
+    #[error("admin routes are disabled")]
+    AdminDisabled,
+    #[error("too many connections from this address")]
+    TooManyConnections,
+    #[error("the server has as many rooms as it can hold, try again later")]
+    TooManyRooms,
+    #[error("room is busy, try again shortly")]
+    RoomBusy,
+    #[error("idempotency key already used for a different request")]
+    IdempotencyKeyReused,
+    #[error("protocol version {0} not supported, the server speaks version {PROTOCOL_VERSION}")]
+    ProtocolMismatch(u32),
+    #[error(transparent)]
     RoomError(#[from] RoomError),
+    // every problem with a request, by the field it's in
+    #[error("{}", join_errors(.0))]
+    InvalidFields(Vec<(&'static str, ServerError)>),
+}
+
+fn join_errors(errors: &[(&'static str, ServerError)]) -> String {
+    errors
+        .iter()
+        .map(|(_, err)| err.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl ServerError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::RoomNotFound => "room_not_found",
+            Self::InvalidCode(_) => "invalid_code",
+            Self::InvalidUsername(_) => "invalid_username",
+            Self::InvalidPassword(_) => "invalid_password",
+            Self::MissingToken => "missing_token",
+            Self::InvalidToken => "invalid_token",
+            Self::AdminDisabled => "admin_disabled",
+            Self::TooManyConnections => "too_many_connections",
+            Self::TooManyRooms => "too_many_rooms",
+            Self::RoomBusy => "room_busy",
+            Self::IdempotencyKeyReused => "idempotency_key_reused",
+            Self::ProtocolMismatch(_) => "protocol_mismatch",
+            Self::RoomError(err) => err.code(),
+            // the first problem's code, so clients that only look at one still understand it
+            Self::InvalidFields(errors) => errors
+                .first()
+                .map_or("invalid_fields", |(_, err)| err.code()),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::RoomNotFound | Self::AdminDisabled => StatusCode::NOT_FOUND,
+            Self::InvalidCode(_)
+            | Self::InvalidFields(_)
+            | Self::InvalidUsername(_)
+            | Self::InvalidPassword(_)
+            | Self::ProtocolMismatch(_) => StatusCode::BAD_REQUEST,
+            Self::MissingToken => StatusCode::UNAUTHORIZED,
+            Self::InvalidToken => StatusCode::FORBIDDEN,
+            Self::TooManyConnections => StatusCode::TOO_MANY_REQUESTS,
+            Self::TooManyRooms | Self::RoomBusy => StatusCode::SERVICE_UNAVAILABLE,
+            Self::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::RoomError(err) => match err {
+                RoomError::GameStarted
+                | RoomError::PlayerExists(_)
+                | RoomError::PlayerConnected(_)
+                | RoomError::PlayerDisconnected(_) => StatusCode::CONFLICT,
+                RoomError::PlayerNotFound(_)
+                | RoomError::ChatNotFound(_)
+                | RoomError::RoundNotFound(_) => StatusCode::NOT_FOUND,
+                RoomError::IncorrectPassword | RoomError::NotHost | RoomError::NotAuthor => {
+                    StatusCode::FORBIDDEN
+                }
+                RoomError::InvalidMessage
+                | RoomError::InvalidSetting(_)
+                | RoomError::InvalidText(_) => StatusCode::BAD_REQUEST,
+                RoomError::WrongPhase
+                | RoomError::BidNotLower(_)
+                | RoomError::AlreadyPassed(_)
+                | RoomError::NotSolver
+                | RoomError::NothingToUndo
+                | RoomError::ColorTaken
+                | RoomError::GameNotOver
+                | RoomError::PlayersNotReady
+                | RoomError::RoomFull
+                | RoomError::HostCannotLeave => StatusCode::CONFLICT,
+                RoomError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ErrorBody {
+    error: Arc<str>,
+    message: Arc<str>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<Arc<str>, Arc<str>>,
 }
 
 impl IntoResponse for ServerError {
     fn into_response(self) -> Response {
+        let fields = match &self {
+            Self::InvalidFields(errors) => errors
+                .iter()
+                .map(|(field, err)| ((*field).into(), err.to_string().into()))
+                .collect(),
+            _ => BTreeMap::new(),
+        };
         (
-            match self {
-                Self::RoomNotFound => StatusCode::NOT_FOUND,
-                Self::MissingUsername | Self::InvalidUsername | Self::InvalidPassword => {
-                    StatusCode::BAD_REQUEST
-                }
-                Self::MissingToken => StatusCode::UNAUTHORIZED,
-                Self::InvalidToken => StatusCode::FORBIDDEN,
-                Self::RoomError(ref err) => match err {
-                    RoomError::GameStarted => StatusCode::CONFLICT,
-                    _ => todo!(),
-                },
-            },
-            self,
+            self.status(),
+            Json(ErrorBody {
+                error: self.code().into(),
+                message: self.to_string().into(),
+                fields,
+            }),
         )
             .into_response()
     }
 }
 
-pub fn init_game_server() -> Router {
-    let rooms = HashMap::<Arc<str>, Arc<Mutex<Room>>>::new();
+impl FromRequestParts<ServerState> for RoomCode {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let Path(code) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        RoomCode::validate(&code, state.code_len)
+            .map_err(|err| ServerError::InvalidCode(err).into_response())
+    }
+}
+
+/// proof that a request carried the admin token.
+struct Admin;
+
+impl FromRequestParts<ServerState> for Admin {
+    type Rejection = ServerError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let expected = state
+            .admin_token
+            .as_ref()
+            .ok_or(ServerError::AdminDisabled)?;
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .ok_or(ServerError::MissingToken)?
+            .to_str()
+            .map_err(|_| ServerError::InvalidToken)?
+            .strip_prefix("Bearer ")
+            .ok_or(ServerError::InvalidToken)?;
+
+        if bool::from(token.as_bytes().ct_eq(expected.expose())) {
+            Ok(Admin)
+        } else {
+            Err(ServerError::InvalidToken)
+        }
+    }
+}
+
+/// lets the binary act on the game server from outside the request handlers.
+#[derive(Debug, Clone)]
+pub struct GameServerHandle {
+    state: ServerState,
+}
+
+impl GameServerHandle {
+    /// warns every connected client that the server is going down, then waits out the grace
+    /// period so they have a chance to react before connections are dropped.
+    pub async fn shutdown(&self, grace: Duration) {
+        let rooms = self
+            .state
+            .rooms
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for room in &rooms {
+            let mut room = room.lock().await;
+            let span = tracing::info_span!("room", code = %room.code());
+            room.announce_shutdown(grace.as_secs())
+                .instrument(span)
+                .await;
+        }
+
+        if let Err(err) = self.persist().await {
+            tracing::error!("failed to save rooms on shutdown: {err}");
+        }
+
+        tokio::time::sleep(grace).await;
+
+        for room in &rooms {
+            room.lock().await.close_all(CloseReason::ServerShutdown);
+        }
+    }
+
+    /// liveness and metrics routes, kept separate so they can be served outside the api prefix.
+    pub fn monitoring(&self) -> Router {
+        Router::new()
+            .route("/health", get(|| async { StatusCode::OK }))
+            .route("/metrics", get(handle_metrics))
+            .with_state(self.state.clone())
+    }
+
+    /// drops rooms whose game ended longer than `results_ttl` ago, and rooms whose host hasn't
+    /// connected within `host_connect_timeout` of creating them.
+    async fn sweep(&self) {
+        let rooms = self
+            .state
+            .rooms
+            .lock()
+            .await
+            .iter()
+            .map(|(code, room)| (code.clone(), room.clone()))
+            .collect::<Vec<_>>();
+
+        let mut expired = Vec::new();
+        for (code, room) in rooms {
+            // a busy room is checked again next time
+            let Ok(room) = lock_room(&self.state, &room).await else {
+                continue;
+            };
+            let finished = room.finished();
+            let created = room.awaiting_host();
+            if finished.is_some_and(|at| at.elapsed() >= self.state.results_ttl)
+                || created.is_some_and(|at| at.elapsed() >= self.state.host_connect_timeout)
+            {
+                expired.push(code);
+            }
+        }
+        if expired.is_empty() {
+            return;
+        }
 
-    Router::new()
-        .route("/rooms", get(|| async {}))
+        let mut rooms = self.state.rooms.lock().await;
+        for code in &expired {
+            rooms.remove(code);
+        }
+        tracing::debug!("dropped {} finished or abandoned rooms", expired.len());
+    }
+
+    /// saves every room to the configured persist path, if there is one.
+    pub async fn persist(&self) -> io::Result<()> {
+        let Some(path) = &self.state.persist_path else {
+            return Ok(());
+        };
+
+        let rooms = self
+            .state
+            .rooms
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut snapshots = Vec::with_capacity(rooms.len());
+        for room in rooms {
+            snapshots.push(room.lock().await.snapshot());
+        }
+
+        persistence::save(path, &snapshots).await?;
+        tracing::debug!("saved {} rooms to {}", snapshots.len(), path.display());
+        Ok(())
+    }
+}
+
+/// shares a room between handlers and starts the clock that drives its phase timers. the clock
+/// stops by itself once the room is dropped.
+fn open_room(room: Room) -> Arc<Mutex<Room>> {
+    let span = tracing::info_span!("room", code = %room.code());
+    let room = Arc::new(Mutex::new(room));
+
+    let weak = Arc::downgrade(&room);
+    tokio::spawn(
+        async move {
+            let mut interval = tokio::time::interval(TICK_PERIOD);
+            loop {
+                interval.tick().await;
+                let Some(room) = weak.upgrade() else {
+                    break;
+                };
+                room.lock().await.tick().await;
+            }
+        }
+        .instrument(span),
+    );
+
+    room
+}
+
+#[cfg(not(feature = "client"))]
+fn cors(config: &ServerConfig) -> tower_http::cors::CorsLayer {
+    use axum::http::header::CONTENT_TYPE;
+    use tower_http::cors::{AllowOrigin, CorsLayer};
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(config.cors_origins.clone()))
+        .allow_methods(config.cors_methods.clone())
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE])
+        .allow_credentials(config.cors_credentials)
+}
+
+fn restore_rooms(
+    config: &ServerConfig,
+    options: &RoomOptions,
+) -> HashMap<Arc<str>, Arc<Mutex<Room>>> {
+    let Some(path) = config.persist_path.as_ref().filter(|path| path.exists()) else {
+        return HashMap::new();
+    };
+
+    match persistence::load(path) {
+        Ok(snapshots) => {
+            tracing::info!("restored {} rooms from {}", snapshots.len(), path.display());
+            snapshots
+                .into_iter()
+                .map(|snapshot| {
+                    (
+                        snapshot.code(),
+                        open_room(Room::restore(snapshot, options.clone())),
+                    )
+                })
+                .collect()
+        }
+        Err(err) => {
+            tracing::error!("failed to restore rooms from {}: {err}", path.display());
+            HashMap::new()
+        }
+    }
+}
+
+pub fn init_game_server(config: &ServerConfig) -> (Router, GameServerHandle) {
+    let filter = match &config.word_list {
+        Some(path) => WordFilter::load(path).unwrap_or_else(|err| {
+            panic!("failed to read word list from {}: {err}", path.display())
+        }),
+        None => WordFilter::default(),
+    };
+    let room_options = RoomOptions {
+        token_len: config.token_len,
+        channel_capacity: config.channel_capacity,
+        filter: Arc::new(filter),
+        counters: Arc::default(),
+    };
+
+    let state = GameServer {
+        rooms: Mutex::new(restore_rooms(config, &room_options)),
+        code_len: config.code_len,
+        room_options,
+        persist_path: config.persist_path.clone(),
+        secure_cookies: config.secure_cookies,
+        evict_connections: config.evict_connections,
+        compression: config.compression,
+        admin_token: config
+            .admin_token
+            .as_deref()
+            .map(|token| Token::from_bytes(token.as_bytes())),
+        created: Mutex::new(HashMap::new()),
+        max_connections_per_ip: config.max_connections_per_ip,
+        results_ttl: Duration::from_secs(config.results_ttl_seconds),
+        host_connect_timeout: Duration::from_secs(config.host_connect_timeout_seconds),
+        max_rooms: config.max_rooms,
+        busy_timeout: Duration::from_millis(config.busy_timeout_millis),
+        max_message_bytes: config.max_message_bytes,
+        connections_per_ip: std::sync::Mutex::new(HashMap::new()),
+        messages_sent: AtomicUsize::new(0),
+        started: Instant::now(),
+    };
+
+    let state = Arc::new(state);
+
+    let router = Router::new()
+        .route("/rooms", get(handle_list))
         .route("/rooms/create", post(handle_create))
-        .route("/rooms/{code}", get(|| async {}))
+        .route("/rooms/{code}", get(handle_summary))
+        .route(
+            "/rooms/{code}/requires-password",
+            get(handle_requires_password),
+        )
+        .route("/rooms/{code}/results", get(handle_results))
+        .route("/rooms/{code}/spectators", get(handle_spectators))
         .route("/rooms/{code}/join", post(handle_join))
+        .route("/rooms/{code}/rejoin", post(handle_rejoin))
+        .route("/rooms/{code}/leave", post(handle_leave))
+        .route("/rooms/{code}/start", post(handle_start))
+        .route("/rooms/{code}/reset", post(handle_reset))
+        .route("/rooms/{code}/settings", post(handle_settings))
+        .route("/rooms/{code}/bots", post(handle_add_bot))
         .route("/rooms/{code}/ws", get(websocket_handler))
-        .with_state(Arc::new(Mutex::new(rooms)))
+        .route("/admin/rooms/{code}/log", get(handle_admin_log))
+        .route("/admin/rooms/{code}/state", get(handle_admin_state))
+        .route("/admin/stats", get(handle_admin_stats))
+        .route("/rooms/{code}/spectate", get(spectate_handler))
+        .with_state(state.clone());
+
+    // the bundled client is served from the same origin, so only a separate frontend needs cors
+    #[cfg(not(feature = "client"))]
+    let router = router.layer(cors(config));
+
+    let handle = GameServerHandle { state };
+
+    {
+        let handle = handle.clone();
+        // short timeouts are swept more often, so rooms don't linger well past them
+        let period = SWEEP_PERIOD
+            .min(Duration::from_secs(config.results_ttl_seconds))
+            .min(Duration::from_secs(config.host_connect_timeout_seconds));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                handle.sweep().await;
+            }
+        });
+    }
+
+    if config.persist_path.is_some() {
+        let handle = handle.clone();
+        let period = Duration::from_secs(config.persist_interval_seconds);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                if let Err(err) = handle.persist().await {
+                    tracing::error!("failed to save rooms: {err}");
+                }
+            }
+        });
+    }
+
+    (router, handle)
 }
 
-// TODO sanitize strings
+// converted into `CreateParams` before anything else looks at it
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct CreateRequest {
-    username: Arc<str>,
+    username: Option<Arc<str>>,
     password: Option<Arc<str>>,
+    /// fixes the board layout, so a game can be reproduced. picked at random if missing.
+    seed: Option<u64>,
+    #[serde(flatten)]
+    settings: RoomSettings,
+}
+
+/// a `CreateRequest` with every field checked. the name still has to be screened against the
+/// server's word filter.
+#[derive(Clone, Debug)]
+struct CreateParams {
+    username: Username,
+    password: Option<Password>,
+    seed: Option<u64>,
+    settings: RoomSettings,
+}
+
+impl TryFrom<CreateRequest> for CreateParams {
+    type Error = ServerError;
+
+    // checked all at once, so a client can fix everything in one go
+    fn try_from(request: CreateRequest) -> Result<Self, Self::Error> {
+        let mut invalid = Vec::new();
+        let username = request
+            .username
+            .map_or_else(|| Ok(Username::anonymous()), parse_username)
+            .map_err(|err| invalid.push(("username", err)))
+            .ok();
+        let password = parse_password(request.password)
+            .map_err(|err| invalid.push(("password", err)))
+            .ok();
+        if let Err(err) = request.settings.validate() {
+            let field = match err {
+                RoomError::InvalidSetting(field) => field,
+                _ => "settings",
+            };
+            invalid.push((field, err.into()));
+        }
+
+        match (username, password) {
+            (Some(username), Some(password)) if invalid.is_empty() => Ok(Self {
+                username,
+                password,
+                seed: request.seed,
+                settings: request.settings,
+            }),
+            _ => Err(ServerError::InvalidFields(invalid)),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct CreateResponse {
     code: Arc<str>,
-    token: Arc<str>,
+    token: Token,
+    // as stored, which may differ from what was asked for
+    username: Username,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RequiresPasswordResponse {
+    requires_password: bool,
 }
 
-// TODO sanitize strings
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct JoinRequest {
-    username: Arc<str>,
+    username: Option<Arc<str>>,
     password: Option<Arc<str>>,
 }
 
+/// a `JoinRequest` with every field checked, like `CreateParams`. a missing name is left for the
+/// room to fill in.
+#[derive(Clone, Debug)]
+struct JoinParams {
+    username: Option<Username>,
+    password: Option<Password>,
+}
+
+impl TryFrom<JoinRequest> for JoinParams {
+    type Error = ServerError;
+
+    fn try_from(request: JoinRequest) -> Result<Self, Self::Error> {
+        let mut invalid = Vec::new();
+        let username = request
+            .username
+            .map(parse_username)
+            .transpose()
+            .map_err(|err| invalid.push(("username", err)))
+            .ok();
+        let password = parse_password(request.password)
+            .map_err(|err| invalid.push(("password", err)))
+            .ok();
+
+        match (username, password) {
+            (Some(username), Some(password)) => Ok(Self { username, password }),
+            _ => Err(ServerError::InvalidFields(invalid)),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct JoinResponse {
-    token: Arc<str>,
+    token: Token,
+    // as stored, which may differ from what was asked for
+    username: Arc<str>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct AddBotRequest {
+    // extra moves added to every bid, to make the bot beatable
+    #[serde(default)]
+    handicap: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct AddBotResponse {
+    username: Arc<str>,
 }
 
-fn generate_code() -> Arc<str> {
-    let mut code = String::with_capacity(CODE_LEN);
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Metrics {
+    rooms: usize,
+    connected_players: usize,
+    spectators: usize,
+    messages_sent: usize,
+}
 
-    for _ in 0..CODE_LEN {
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Stats {
+    rooms: usize,
+    players: usize,
+    connected_players: usize,
+    spectators: usize,
+    rooms_created: usize,
+    players_joined: usize,
+    games_started: usize,
+    games_finished: usize,
+    uptime_seconds: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StartQuery {
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ResetQuery {
+    /// deals the same board again rather than a new one.
+    #[serde(default)]
+    same_seed: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct WebsocketQuery {
+    v: Option<u32>,
+    // opts into `ServerMessage::PlayerUpdate`
+    #[serde(default)]
+    player_updates: bool,
+    // swaps single moves for `ServerMessage::BoardState`
+    #[serde(default)]
+    coalesce_moves: bool,
+    #[serde(default)]
+    format: Format,
+    compress: Option<Compression>,
+    /// the id of the last chat the client saw, to be caught up on only the ones after it.
+    since: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SpectateQuery {
+    v: Option<u32>,
+    /// shown in the room's spectator list. spectators without one are only counted.
+    name: Option<Arc<str>>,
+    #[serde(default)]
+    player_updates: bool,
+    #[serde(default)]
+    coalesce_moves: bool,
+    #[serde(default)]
+    format: Format,
+    compress: Option<Compression>,
+}
+
+/// turns away clients that asked for a protocol version other than ours, before upgrading them.
+fn check_version(version: Option<u32>) -> Result<(), ServerError> {
+    match version {
+        Some(version) if version != PROTOCOL_VERSION => Err(ServerError::ProtocolMismatch(version)),
+        _ => Ok(()),
+    }
+}
+
+/// the optional messages a connection asked for.
+#[derive(Clone, Copy, Debug)]
+struct OptIns {
+    player_updates: bool,
+    coalesce_moves: bool,
+}
+
+impl OptIns {
+    /// whether a client should be sent `message`, given what it opted into.
+    fn wants(self, message: &ServerMessage) -> bool {
+        match message {
+            ServerMessage::PlayerUpdate { .. } => self.player_updates,
+            ServerMessage::RobotMoved { .. } | ServerMessage::MoveUndone { .. } => {
+                !self.coalesce_moves
+            }
+            ServerMessage::BoardState { .. } => self.coalesce_moves,
+            _ => true,
+        }
+    }
+}
+
+/// the close frame for a connection the room closed, or `None` if the room itself went away.
+fn close_frame(reason: Option<CloseReason>) -> CloseFrame {
+    let (code, reason) = match reason {
+        Some(CloseReason::Replaced) => (CLOSE_REPLACED, "replaced by a newer connection"),
+        Some(CloseReason::Left) => (CLOSE_LEFT, "left the room"),
+        Some(CloseReason::Kicked) => (CLOSE_KICKED, "kicked by the host"),
+        Some(CloseReason::ServerShutdown) => (close_code::AWAY, "server shutting down"),
+        None => (CLOSE_ROOM_CLOSED, "room closed"),
+    };
+    CloseFrame {
+        code,
+        reason: reason.into(),
+    }
+}
+
+fn generate_code(len: usize) -> Arc<str> {
+    let mut code = String::with_capacity(len);
+
+    for _ in 0..len {
         code.push(CODE_CHARS[rng().random_range(..NUM_CODE_CHARS)]);
     }
 
     code.into()
 }
 
-async fn handle_join(
-    Path(code): Path<String>,
-    State(rooms): State<ServerState>,
-    Json(payload): Json<JoinRequest>,
-) -> Result<impl IntoResponse, ServerError> {
-    let room = rooms
+fn validate_username(state: &GameServer, username: Arc<str>) -> Result<Username, ServerError> {
+    Username::validate_with(username, &state.room_options.filter)
+        .map_err(ServerError::InvalidUsername)
+}
+
+/// checks a name's format, leaving the word filter to `screen_username`.
+fn parse_username(username: Arc<str>) -> Result<Username, ServerError> {
+    Username::validate(username).map_err(ServerError::InvalidUsername)
+}
+
+/// reported like the rest of a request body's fields.
+fn screen_username(state: &GameServer, username: Username) -> Result<Username, ServerError> {
+    username.screen(&state.room_options.filter).map_err(|err| {
+        ServerError::InvalidFields(vec![("username", ServerError::InvalidUsername(err))])
+    })
+}
+
+fn parse_password(password: Option<Arc<str>>) -> Result<Option<Password>, ServerError> {
+    password
+        .map(Password::validate)
+        .transpose()
+        .map_err(ServerError::InvalidPassword)
+}
+
+async fn get_room(state: &GameServer, code: &RoomCode) -> Result<Arc<Mutex<Room>>, ServerError> {
+    Ok(state
+        .rooms
         .lock()
         .await
-        .get(&Arc::<str>::from(code))
+        .get(code.as_str())
         .ok_or(ServerError::RoomNotFound)?
-        .clone();
+        .clone())
+}
+
+/// locks the room for a request, giving up if it's been held too long, e.g. by a send to a player
+/// whose connection has stalled.
+async fn lock_room<'a>(
+    state: &GameServer,
+    room: &'a Mutex<Room>,
+) -> Result<MutexGuard<'a, Room>, ServerError> {
+    timeout(state.busy_timeout, room.lock())
+        .await
+        .map_err(|_| ServerError::RoomBusy)
+}
 
-    let token = room
+async fn handle_metrics(State(state): State<ServerState>) -> Json<Metrics> {
+    let rooms = state
+        .rooms
         .lock()
         .await
-        .join(payload.username, payload.password)
-        .await?;
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
 
-    Ok(Json(JoinResponse {
-        token: STANDARD.encode(token).into(),
-    }))
+    let mut metrics = Metrics {
+        rooms: rooms.len(),
+        connected_players: 0,
+        spectators: 0,
+        messages_sent: state.messages_sent.load(Ordering::Relaxed),
+    };
+    // a busy room is left out rather than holding up the rest
+    for room in rooms {
+        let Ok(room) = lock_room(&state, &room).await else {
+            continue;
+        };
+        let summary = room.summary();
+        metrics.connected_players += summary.connected_count;
+        metrics.spectators += summary.spectator_count;
+    }
+
+    Json(metrics)
+}
+
+async fn handle_list(State(state): State<ServerState>) -> Json<Vec<RoomSummary>> {
+    let rooms = state
+        .rooms
+        .lock()
+        .await
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut summaries = Vec::with_capacity(rooms.len());
+    for room in rooms {
+        if let Ok(room) = lock_room(&state, &room).await
+            && !room.is_private()
+        {
+            summaries.push(room.summary());
+        }
+    }
+
+    Json(summaries)
+}
+
+async fn handle_summary(
+    code: RoomCode,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let summary = lock_room(&state, &room).await?.summary();
+
+    Ok(Json(summary))
+}
+
+/// lets a join form decide whether to ask for a password, without giving away anything else.
+async fn handle_requires_password(
+    code: RoomCode,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let requires_password = lock_room(&state, &room).await?.requires_password();
+
+    Ok(Json(RequiresPasswordResponse { requires_password }))
+}
+
+async fn handle_spectators(
+    code: RoomCode,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let spectators = lock_room(&state, &room).await?.spectators();
+
+    Ok(Json(spectators))
+}
+
+/// the final standings of a finished game, kept for a while after it ends.
+async fn handle_results(
+    code: RoomCode,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let results = lock_room(&state, &room).await?.results()?;
+
+    Ok(Json(results))
+}
+
+/// the messages a room sent most recently, for debugging a game gone wrong.
+async fn handle_admin_log(
+    _: Admin,
+    code: RoomCode,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let log = lock_room(&state, &room).await?.log();
+
+    Ok(Json(log))
+}
+
+/// everything about a room but its secrets, for debugging.
+async fn handle_admin_state(
+    _: Admin,
+    code: RoomCode,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let room_state = lock_room(&state, &room).await?.state();
+
+    Ok(Json(room_state))
+}
+
+/// server-wide totals, for a dashboard.
+async fn handle_admin_stats(_: Admin, State(state): State<ServerState>) -> Json<Stats> {
+    let rooms = state
+        .rooms
+        .lock()
+        .await
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let counters = &state.room_options.counters;
+    let mut stats = Stats {
+        rooms: rooms.len(),
+        players: 0,
+        connected_players: 0,
+        spectators: 0,
+        rooms_created: counters.rooms_created.load(Ordering::Relaxed),
+        players_joined: counters.players_joined.load(Ordering::Relaxed),
+        games_started: counters.games_started.load(Ordering::Relaxed),
+        games_finished: counters.games_finished.load(Ordering::Relaxed),
+        uptime_seconds: state.started.elapsed().as_secs(),
+    };
+    // a busy room is left out rather than holding up the rest
+    for room in rooms {
+        let Ok(room) = lock_room(&state, &room).await else {
+            continue;
+        };
+        let summary = room.summary();
+        stats.players += summary.player_count;
+        stats.connected_players += summary.connected_count;
+        stats.spectators += summary.spectator_count;
+    }
+
+    Json(stats)
+}
+
+#[tracing::instrument(name = "room", skip_all, fields(code = code.as_str()))]
+async fn handle_join(
+    code: RoomCode,
+    cookies: CookieJar,
+    State(state): State<ServerState>,
+    Json(payload): Json<JoinRequest>,
+) -> Result<impl IntoResponse, ServerError> {
+    let params = JoinParams::try_from(payload)?;
+    let room = get_room(&state, &code).await?;
+
+    let mut room = lock_room(&state, &room).await?;
+    let username = match params.username {
+        Some(username) => screen_username(&state, username)?,
+        None => room.anonymous_username(),
+    };
+
+    let token = room.join(username.clone(), params.password).await?;
+
+    Ok((
+        cookies.add(token_cookie(&state, &token)),
+        Json(JoinResponse {
+            token,
+            username: username.into(),
+        }),
+    ))
+}
+
+#[tracing::instrument(name = "room", skip_all, fields(code = code.as_str()))]
+async fn handle_rejoin(
+    code: RoomCode,
+    cookies: CookieJar,
+    State(state): State<ServerState>,
+    Json(payload): Json<JoinRequest>,
+) -> Result<impl IntoResponse, ServerError> {
+    let params = JoinParams::try_from(payload)?;
+    let room = get_room(&state, &code).await?;
+    // rejoining can't make up a name, so a missing one is as good as empty
+    let username = params.username.ok_or_else(|| {
+        ServerError::InvalidFields(vec![(
+            "username",
+            ServerError::InvalidUsername(ValidationError::Empty),
+        )])
+    })?;
+    let username = screen_username(&state, username)?;
+
+    let (username, token) = lock_room(&state, &room)
+        .await?
+        .rejoin(username, params.password)?;
+
+    Ok((
+        cookies.add(token_cookie(&state, &token)),
+        Json(JoinResponse { token, username }),
+    ))
 }
 
 async fn handle_create(
-    State(rooms): State<ServerState>,
+    client: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    cookies: CookieJar,
+    State(state): State<ServerState>,
     Json(payload): Json<CreateRequest>,
 ) -> Result<impl IntoResponse, ServerError> {
-    let mut code = generate_code();
-    while rooms.lock().await.contains_key(&code) {
-        code = generate_code();
+    let key = headers
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|key| key.to_str().ok());
+    let response = match key {
+        Some(key) => {
+            // another client's key is theirs alone, even if it happens to match
+            let key = (client.map(|client| client.0.0.ip()), key.into());
+            let request = serde_json::to_vec(&payload).unwrap_or_default();
+            let request: [u8; 32] = Sha256::digest(request).into();
+
+            // held throughout so concurrent retries can't both create a room
+            let mut created = state.created.lock().await;
+            let now = Instant::now();
+            created.retain(|_, created| now.duration_since(created.at) < IDEMPOTENCY_TTL);
+
+            match created.get(&key) {
+                Some(created) if created.request == request => created.response.clone(),
+                Some(_) => return Err(ServerError::IdempotencyKeyReused),
+                None => {
+                    let response = create_room(&state, payload).await?;
+                    created.insert(
+                        key,
+                        Created {
+                            at: now,
+                            request,
+                            response: response.clone(),
+                        },
+                    );
+                    response
+                }
+            }
+        }
+        None => create_room(&state, payload).await?,
+    };
+
+    Ok((
+        cookies.add(token_cookie(&state, &response.token)),
+        Json(response),
+    ))
+}
+
+async fn create_room(
+    state: &GameServer,
+    payload: CreateRequest,
+) -> Result<CreateResponse, ServerError> {
+    let params = CreateParams::try_from(payload)?;
+    let username = screen_username(state, params.username)?;
+
+    // held until the room is in, so concurrent creates can't overshoot the cap
+    let mut rooms = state.rooms.lock().await;
+    if rooms.len() >= state.max_rooms {
+        return Err(ServerError::TooManyRooms);
+    }
+    let mut code = generate_code(state.code_len);
+    while rooms.contains_key(&code) {
+        code = generate_code(state.code_len);
     }
 
-    let (room, host_token) = Room::create(payload.username, payload.password);
+    let (room, host_token) = Room::create(
+        code.clone(),
+        username.clone(),
+        params.password,
+        params.settings,
+        params.seed.unwrap_or_else(|| rng().random()),
+        state.room_options.clone(),
+    );
 
-    rooms
-        .lock()
-        .await
-        .insert(code.clone(), Arc::new(Mutex::new(room)));
+    rooms.insert(code.clone(), open_room(room));
 
-    Ok(Json(CreateResponse {
+    Ok(CreateResponse {
         code,
-        token: STANDARD.encode(host_token).into(),
-    }))
+        token: host_token,
+        username,
+    })
+}
+
+/// lets browsers authenticate the websocket upgrade without handling the token themselves.
+fn token_cookie(state: &GameServer, token: &Token) -> Cookie<'static> {
+    Cookie::build(("token", token.encode()))
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .path("/")
+        .secure(state.secure_cookies)
+        .build()
+}
+
+/// the token from a `Sec-WebSocket-Protocol: bearer, <token>` header, the only place browsers can
+/// put it on a websocket upgrade besides a cookie.
+fn subprotocol_token(headers: &HeaderMap) -> Option<&str> {
+    let mut protocols = headers
+        .get(SEC_WEBSOCKET_PROTOCOL)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .map(str::trim);
+
+    if protocols.next()? == BEARER_PROTOCOL {
+        protocols.next()
+    } else {
+        None
+    }
+}
+
+async fn authenticate(
+    state: &GameServer,
+    room: &Mutex<Room>,
+    headers: &HeaderMap,
+    cookies: &CookieJar,
+) -> Result<Arc<str>, ServerError> {
+    let token = if let Some(auth_header) = headers.get(AUTHORIZATION) {
+        auth_header
+            .to_str()
+            .map_err(|_| ServerError::InvalidToken)?
+            .strip_prefix("Bearer ")
+            .ok_or(ServerError::InvalidToken)?
+    } else if let Some(token) = subprotocol_token(headers) {
+        token
+    } else {
+        cookies
+            .get("token")
+            .ok_or(ServerError::MissingToken)?
+            .value()
+    };
+    let token = Token::decode(token).ok_or(ServerError::InvalidToken)?;
+
+    lock_room(state, room)
+        .await?
+        .authenticate(&token)
+        .ok_or(ServerError::InvalidToken)
+}
+
+#[tracing::instrument(name = "room", skip_all, fields(code = code.as_str()))]
+async fn handle_leave(
+    headers: HeaderMap,
+    cookies: CookieJar,
+    code: RoomCode,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let username = authenticate(&state, &room, &headers, &cookies).await?;
+
+    lock_room(&state, &room).await?.leave(username).await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(name = "room", skip_all, fields(code = code.as_str()))]
+async fn handle_add_bot(
+    headers: HeaderMap,
+    cookies: CookieJar,
+    code: RoomCode,
+    State(state): State<ServerState>,
+    Json(payload): Json<AddBotRequest>,
+) -> Result<impl IntoResponse, ServerError> {
+    if payload.handicap > bot::MAX_HANDICAP {
+        return Err(RoomError::InvalidSetting("handicap").into());
+    }
+    let room = get_room(&state, &code).await?;
+    let username = authenticate(&state, &room, &headers, &cookies).await?;
+
+    let (bot, receiver) = lock_room(&state, &room).await?.add_bot(username).await?;
+    tokio::spawn(bot::run(
+        Arc::downgrade(&room),
+        bot.clone(),
+        receiver,
+        payload.handicap,
+    ));
+
+    Ok(Json(AddBotResponse { username: bot }))
+}
+
+#[tracing::instrument(name = "room", skip_all, fields(code = code.as_str()))]
+async fn handle_start(
+    headers: HeaderMap,
+    cookies: CookieJar,
+    code: RoomCode,
+    Query(query): Query<StartQuery>,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let username = authenticate(&state, &room, &headers, &cookies).await?;
+
+    lock_room(&state, &room)
+        .await?
+        .start(username, query.force)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// takes a finished game back to the lobby for a rematch.
+#[tracing::instrument(name = "room", skip_all, fields(code = code.as_str()))]
+async fn handle_reset(
+    headers: HeaderMap,
+    cookies: CookieJar,
+    code: RoomCode,
+    Query(query): Query<ResetQuery>,
+    State(state): State<ServerState>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let username = authenticate(&state, &room, &headers, &cookies).await?;
+    let seed = (!query.same_seed).then(|| rng().random());
+
+    lock_room(&state, &room)
+        .await?
+        .reset(username, seed)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// replaces the room's settings, taking the same fields as creating it.
+#[tracing::instrument(name = "room", skip_all, fields(code = code.as_str()))]
+async fn handle_settings(
+    headers: HeaderMap,
+    cookies: CookieJar,
+    code: RoomCode,
+    State(state): State<ServerState>,
+    Json(settings): Json<RoomSettings>,
+) -> Result<impl IntoResponse, ServerError> {
+    let room = get_room(&state, &code).await?;
+    let username = authenticate(&state, &room, &headers, &cookies).await?;
+
+    lock_room(&state, &room)
+        .await?
+        .configure(username, settings)
+        .await?;
+
+    Ok(StatusCode::OK)
 }
 
 async fn websocket_handler(
     ws: WebSocketUpgrade,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     cookies: CookieJar,
-    Path(code): Path<String>,
-    State(rooms): State<ServerState>,
-) -> Result<impl IntoResponse, ServerError> {
-    let room = rooms
-        .lock()
-        .await
-        .get(code.as_str())
-        .ok_or(ServerError::RoomNotFound)?
-        .clone();
+    code: RoomCode,
+    Query(query): Query<WebsocketQuery>,
+    State(state): State<ServerState>,
+) -> Result<Response, ServerError> {
+    check_version(query.v)?;
+
+    let room = get_room(&state, &code).await?;
 
     tracing::debug!("got room");
 
-    let token = STANDARD
-        .decode(if let Some(auth_header) = headers.get("Authorization") {
-            auth_header
-                .to_str()
-                .map_err(|_| ServerError::InvalidToken)?
-                .strip_prefix("Bearer ")
-                .ok_or(ServerError::InvalidToken)?
-        } else {
-            tracing::debug!("trying to get cookie");
-            let cookie = cookies
-                .get("token")
-                .ok_or(ServerError::MissingToken)?
-                .value();
-            tracing::debug!("got cookie: {cookie}");
-            cookie
-        })
-        .map_err(|_| ServerError::InvalidToken)?;
+    let username = authenticate(&state, &room, &headers, &cookies).await?;
 
-    tracing::debug!("got token");
+    tracing::debug!("got name: {username}");
 
-    let username = room
-        .lock()
-        .await
-        .authenticate(token)
-        .ok_or(ServerError::InvalidToken)?;
+    let slot = ConnectionSlot::claim(&state, client.ip())?;
+    let encoding = negotiate(&state, query.format, query.compress);
+    let span = tracing::info_span!("ws", code = code.as_str(), user = %username);
+    let ws = limit_size(ws, &state).protocols([BEARER_PROTOCOL]);
+    Ok(ws.on_upgrade(move |socket| {
+        websocket(socket, state, room, username, query, encoding, slot).instrument(span)
+    }))
+}
 
-    tracing::debug!("got name: {username}");
+/// caps what a client can send in one frame or message. going over errors the socket's stream,
+/// which gets answered with [`refusal`].
+fn limit_size(ws: WebSocketUpgrade, state: &GameServer) -> WebSocketUpgrade {
+    ws.max_frame_size(state.max_message_bytes)
+        .max_message_size(state.max_message_bytes)
+}
 
-    Ok(ws.on_upgrade(|socket| websocket(socket, room, username)))
+/// the close frame for a client whose socket errored. an oversize message breaks the server's
+/// policy, while anything else is reported without the details of what went wrong.
+fn refusal(err: axum::Error) -> CloseFrame {
+    tracing::info!("closing websocket after read error: {err}");
+    let err = err.into_inner();
+    match err.downcast_ref::<tungstenite::Error>() {
+        Some(tungstenite::Error::Capacity(_)) => CloseFrame {
+            code: close_code::POLICY,
+            reason: err.to_string().into(),
+        },
+        _ => CloseFrame {
+            code: close_code::ERROR,
+            reason: "connection error".into(),
+        },
+    }
 }
 
-async fn websocket(socket: WebSocket, room: Arc<Mutex<Room>>, username: Arc<str>) {
+/// the encoding a client asked for, minus anything the server has turned off.
+fn negotiate(state: &GameServer, format: Format, compress: Option<Compression>) -> Encoding {
+    Encoding::new(format, compress.filter(|_| state.compression))
+}
+
+async fn say_hello(socket: &mut WebSocket, encoding: Encoding) -> bool {
+    socket
+        .send(encoding.encode(&ServerMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+        }))
+        .await
+        .is_ok()
+}
+
+async fn websocket(
+    mut socket: WebSocket,
+    state: ServerState,
+    room: Arc<Mutex<Room>>,
+    username: Arc<str>,
+    query: WebsocketQuery,
+    encoding: Encoding,
+    // held until the connection ends
+    _slot: ConnectionSlot,
+) {
     tracing::debug!("handling websocket");
-    let (mut socket_sender, mut socket_receiver) = socket.split();
-    let mut channel_receiver = room
+    if !say_hello(&mut socket, encoding).await {
+        return;
+    }
+
+    let connected = room
         .lock()
         .await
-        .connect(username.clone())
-        .await
-        .expect("player not found");
+        .connect(username.clone(), state.evict_connections, query.since)
+        .await;
+    let Connection {
+        id: connection,
+        messages: mut channel_receiver,
+        mut closed,
+    } = match connected {
+        Ok(connected) => connected,
+        Err(err) => {
+            tracing::info!("refusing websocket: {err}");
+            let code = match err {
+                RoomError::PlayerConnected(_) => CLOSE_ALREADY_CONNECTED,
+                _ => close_code::POLICY,
+            };
+            let _ = socket
+                .send(Message::Close(Some(CloseFrame {
+                    code,
+                    reason: err.to_string().into(),
+                })))
+                .await;
+            return;
+        }
+    };
+
+    let (mut socket_sender, mut socket_receiver) = socket.split();
+    let opt_ins = OptIns {
+        player_updates: query.player_updates,
+        coalesce_moves: query.coalesce_moves,
+    };
 
     tracing::debug!("connected to room");
 
-    let mut send_task = tokio::spawn(async move {
-        while let Some(msg) = channel_receiver.recv().await {
-            if socket_sender
-                .send(Message::text(
-                    serde_json::to_string(&msg).expect("parsing message failed"),
-                ))
-                .await
-                .is_err()
-            {
-                break;
+    // the receive task can't write to the socket, so a refusal goes through the send task
+    let (refuse, mut refused) = oneshot::channel();
+    let mut send_task = tokio::spawn(
+        async move {
+            let mut seq = 0;
+            loop {
+                let msg = tokio::select! {
+                    msg = channel_receiver.recv() => msg,
+                    frame = &mut refused => {
+                        if let Ok(frame) = frame {
+                            let _ = socket_sender.send(Message::Close(Some(frame))).await;
+                        }
+                        return;
+                    }
+                };
+                let Some(msg) = msg else {
+                    break;
+                };
+                if !opt_ins.wants(&msg) {
+                    continue;
+                }
+                let envelope = Envelope { seq, message: &msg };
+                seq += 1;
+                if socket_sender
+                    .send(encoding.encode(&envelope))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                state.messages_sent.fetch_add(1, Ordering::Relaxed);
             }
+
+            // the room dropped our channel, so let the client know why
+            let frame = close_frame(closed.try_recv().ok());
+            tracing::debug!("closing websocket: {}", frame.reason);
+            let _ = socket_sender.send(Message::Close(Some(frame))).await;
         }
-    });
+        .instrument(Span::current()),
+    );
 
     tracing::debug!("spawned send task");
 
     let name2 = username.clone();
     let room2 = room.clone();
-    let mut receive_task = tokio::spawn(async move {
-        while let Some(Ok(Message::Text(json))) = socket_receiver.next().await {
-            tracing::info!("got message {} from {}", json.as_str(), name2);
-            let message = serde_json::from_str::<PlayerMessage>(json.as_str())
-                .expect("parsing player message failed");
-
-            room2
-                .lock()
-                .await
-                .handle_message(name2.clone(), message)
-                .await;
+    let mut receive_task = tokio::spawn(
+        async move {
+            while let Some(frame) = socket_receiver.next().await {
+                let frame = match frame {
+                    Ok(Message::Close(_)) => break,
+                    Ok(frame) => frame,
+                    Err(err) => return Some(refusal(err)),
+                };
+                let Some(message) = encoding.decode(&frame) else {
+                    continue;
+                };
+                match &message {
+                    Ok(PlayerMessage::Ping { .. }) => {}
+                    Ok(message) => tracing::info!("got message {message:?} from {name2}"),
+                    Err(_) => tracing::info!("got undecodable message {frame:?} from {name2}"),
+                }
+
+                let mut room = room2.lock().await;
+                match message {
+                    Ok(message) => room.handle_message(name2.clone(), message).await,
+                    Err(_) => room.reject(name2.clone(), RoomError::InvalidMessage).await,
+                }
+            }
+            None
         }
-    });
+        .instrument(Span::current()),
+    );
 
     tracing::debug!("spawned receive task");
 
     tokio::select! {
         _ = &mut send_task => receive_task.abort(),
-        _ = &mut receive_task => send_task.abort(),
+        refusal = &mut receive_task => {
+            // let the send task close the socket, unless it's already gone
+            let refused = refusal
+                .ok()
+                .flatten()
+                .is_some_and(|frame| refuse.send(frame).is_ok());
+            if refused {
+                let _ = send_task.await;
+            } else {
+                send_task.abort();
+            }
+        }
+    };
+
+    // fails if the player left the room while connected, which is fine
+    let _ = room.lock().await.disconnect(username, connection).await;
+}
+
+async fn spectate_handler(
+    ws: WebSocketUpgrade,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    code: RoomCode,
+    Query(query): Query<SpectateQuery>,
+    State(state): State<ServerState>,
+) -> Result<Response, ServerError> {
+    check_version(query.v)?;
+
+    let name = query
+        .name
+        .map(|name| validate_username(&state, name))
+        .transpose()?;
+    let room = get_room(&state, &code).await?;
+    let span = tracing::info_span!("spectate", code = code.as_str());
+    let (receiver, watching) = {
+        let mut room = lock_room(&state, &room).await?;
+        let password = headers
+            .get(PASSWORD_HEADER)
+            .and_then(|password| password.to_str().ok())
+            .map(Arc::from);
+        room.check_password(parse_password(password)?)?;
+        span.in_scope(|| room.spectate(name))
+    };
+
+    let slot = ConnectionSlot::claim(&state, client.ip())?;
+    let encoding = negotiate(&state, query.format, query.compress);
+    Ok(limit_size(ws, &state).on_upgrade(move |socket| {
+        spectate(
+            socket,
+            state,
+            receiver,
+            OptIns {
+                player_updates: query.player_updates,
+                coalesce_moves: query.coalesce_moves,
+            },
+            encoding,
+            slot,
+            watching,
+        )
+        .instrument(span)
+    }))
+}
+
+async fn spectate(
+    mut socket: WebSocket,
+    state: ServerState,
+    mut channel_receiver: broadcast::Receiver<Arc<ServerMessage>>,
+    opt_ins: OptIns,
+    encoding: Encoding,
+    _slot: ConnectionSlot,
+    _watching: Option<Watching>,
+) {
+    tracing::debug!("handling spectator websocket");
+    if !say_hello(&mut socket, encoding).await {
+        return;
+    }
+
+    let (mut socket_sender, mut socket_receiver) = socket.split();
+
+    let (refuse, mut refused) = oneshot::channel();
+    let mut send_task = tokio::spawn(
+        async move {
+            let mut seq = 0;
+            loop {
+                let msg = tokio::select! {
+                    msg = channel_receiver.recv() => msg,
+                    frame = &mut refused => {
+                        if let Ok(frame) = frame {
+                            let _ = socket_sender.send(Message::Close(Some(frame))).await;
+                        }
+                        break;
+                    }
+                };
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("spectator lagged behind by {skipped} messages");
+                        // leave a gap where they went, so the client knows to resync
+                        seq += skipped;
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                if !opt_ins.wants(&msg) {
+                    continue;
+                }
+                let envelope = Envelope { seq, message: &msg };
+                seq += 1;
+                if socket_sender
+                    .send(encoding.encode(&envelope))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+                state.messages_sent.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        .instrument(Span::current()),
+    );
+
+    // spectators can't act on the room, so anything they send is dropped
+    let mut receive_task = tokio::spawn(async move {
+        while let Some(frame) = socket_receiver.next().await {
+            if let Err(err) = frame {
+                return Some(refusal(err));
+            }
+        }
+        None
+    });
+
+    tokio::select! {
+        _ = &mut send_task => receive_task.abort(),
+        refusal = &mut receive_task => {
+            // let the send task close the socket, unless it's already gone
+            let refused = refusal
+                .ok()
+                .flatten()
+                .is_some_and(|frame| refuse.send(frame).is_ok());
+            if refused {
+                let _ = send_task.await;
+            } else {
+                send_task.abort();
+            }
+        }
     };
 
-    room.lock().await.disconnect(username).await.unwrap();
+    tracing::debug!("spectator disconnected");
 }
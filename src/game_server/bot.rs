@@ -0,0 +1,133 @@
+//! computer players. a bot acts through the same `PlayerMessage`s as everyone else, and only
+//! knows what the room tells it.
+
+use std::sync::{Arc, Weak};
+
+use tokio::{
+    sync::{Mutex, mpsc::Receiver},
+    task,
+};
+
+use super::room::{Phase, PlayerMessage, Room, ServerMessage};
+use crate::game::{Board, Direction, Robot, Target};
+
+pub const MAX_HANDICAP: u32 = 10;
+
+/// plays as `username` until the room goes away. bids are the optimal move count plus
+/// `handicap`, and the bot always plays the optimal solution when it gets to solve.
+pub async fn run(
+    room: Weak<Mutex<Room>>,
+    username: Arc<str>,
+    mut receiver: Receiver<Arc<ServerMessage>>,
+    handicap: u32,
+) {
+    let mut board: Option<Board> = None;
+
+    while let Some(message) = receiver.recv().await {
+        match &*message {
+            ServerMessage::Welcome { board: current, .. }
+            | ServerMessage::GameReset { board: current, .. }
+            | ServerMessage::SettingsChanged { board: current, .. } => {
+                board = Some(current.clone())
+            }
+            ServerMessage::RobotMoved { robot, to, .. }
+            | ServerMessage::MoveUndone { robot, to, .. } => {
+                if let Some(board) = &mut board {
+                    board.place_robot(*robot, *to);
+                }
+            }
+            ServerMessage::PhaseChanged {
+                phase: Phase::Bidding { target, .. },
+                ..
+            } => {
+                let Some(board) = board.clone() else {
+                    continue;
+                };
+                // acting from a separate task keeps this loop draining the channel, so the room
+                // never waits on the bot while the bot waits on the room
+                tokio::spawn(bid(
+                    room.clone(),
+                    username.clone(),
+                    board,
+                    *target,
+                    handicap,
+                ));
+            }
+            ServerMessage::PhaseChanged {
+                phase:
+                    Phase::Solving {
+                        solver,
+                        target,
+                        origin,
+                        ..
+                    },
+                ..
+            } => {
+                let Some(board) = &mut board else {
+                    continue;
+                };
+                board.place_robots(*origin);
+                if *solver == username {
+                    tokio::spawn(solve(
+                        room.clone(),
+                        username.clone(),
+                        board.clone(),
+                        *target,
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn bid(
+    room: Weak<Mutex<Room>>,
+    username: Arc<str>,
+    board: Board,
+    target: Target,
+    handicap: u32,
+) {
+    let message = match search(board, target).await {
+        Some(path) if !path.is_empty() => PlayerMessage::Bid {
+            moves: path.len() as u32 + handicap,
+        },
+        // nothing to bid on if it's out of the bot's reach, or there's nothing to do
+        _ => PlayerMessage::Pass,
+    };
+    act(&room, &username, message).await;
+}
+
+async fn solve(room: Weak<Mutex<Room>>, username: Arc<str>, board: Board, target: Target) {
+    let Some(path) = search(board, target).await else {
+        act(&room, &username, PlayerMessage::Pass).await;
+        return;
+    };
+    for (robot, direction) in path {
+        act(
+            &room,
+            &username,
+            PlayerMessage::Move {
+                robot: Some(robot),
+                direction,
+            },
+        )
+        .await;
+    }
+}
+
+async fn search(board: Board, target: Target) -> Option<Vec<(Robot, Direction)>> {
+    task::spawn_blocking(move || board.solve(target))
+        .await
+        .ok()
+        .flatten()
+}
+
+async fn act(room: &Weak<Mutex<Room>>, username: &Arc<str>, message: PlayerMessage) {
+    if let Some(room) = room.upgrade() {
+        room.lock()
+            .await
+            .handle_message(username.clone(), message)
+            .await;
+    }
+}
@@ -0,0 +1,19 @@
+use std::{io, path::Path};
+
+use tokio::fs;
+
+use super::room::RoomSnapshot;
+
+pub async fn save(path: &Path, rooms: &[RoomSnapshot]) -> io::Result<()> {
+    let json = serde_json::to_vec(rooms)?;
+
+    // written beside the real file and renamed over it so a crash mid-write can't corrupt it
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, json).await?;
+    fs::rename(&temp_path, path).await
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<RoomSnapshot>> {
+    let json = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&json)?)
+}
@@ -1,15 +1,34 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, VecDeque},
+    ops::RangeInclusive,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use axum::extract::ws::{Message, WebSocket};
-use bimap::BiMap;
-use futures_util::{SinkExt, future::join_all, stream::SplitSink, task::waker};
-use rand::{Rng, RngCore, SeedableRng, rng, rngs::StdRng};
-use serde::{Deserialize, Serialize};
+use futures_util::future::join_all;
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, Receiver, Sender},
+        oneshot,
+    },
+    task::{self, JoinHandle},
+    time::Instant,
+};
 
-const TOKEN_LEN: usize = 16;
-const CHANNEL_CAPACITY: usize = 10;
+use super::token::Token;
+use super::validation::{ChatText, Password, Username, ValidationError, WordFilter, fold_username};
+use crate::game::{BOARD_SIZE, BOARD_SIZES, Board, Direction, Position, Robot, Target};
+
+mod examples;
 
 #[derive(Error, Debug, Clone, Serialize)]
 pub enum RoomError {
@@ -25,245 +44,2649 @@ pub enum RoomError {
     PlayerDisconnected(Arc<str>),
     #[error("incorrect password")]
     IncorrectPassword,
+    #[error("only the host can do that")]
+    NotHost,
+    #[error("message could not be parsed")]
+    InvalidMessage,
+    #[error("can't do that right now")]
+    WrongPhase,
+    #[error("bids can only be lowered, and yours is already {0}")]
+    BidNotLower(u32),
+    #[error("player '{0}' already passed")]
+    AlreadyPassed(Arc<str>),
+    #[error("only the solving player can move robots")]
+    NotSolver,
+    #[error("not every player is ready")]
+    PlayersNotReady,
+    #[error("room is full")]
+    RoomFull,
+    #[error("the host can't leave the room")]
+    HostCannotLeave,
+    #[error("setting '{0}' is out of range")]
+    InvalidSetting(&'static str),
+    #[error("sending messages too quickly")]
+    RateLimited,
+    #[error("message invalid: {0}")]
+    InvalidText(ValidationError),
+    #[error("no moves to undo")]
+    NothingToUndo,
+    #[error("another player already has that color")]
+    ColorTaken,
+    #[error("game isn't over yet")]
+    GameNotOver,
+    #[error("chat message {0} not found")]
+    ChatNotFound(u64),
+    #[error("only the author can change a message")]
+    NotAuthor,
+    #[error("round {0} isn't in the history")]
+    RoundNotFound(u32),
+}
+
+impl RoomError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::GameStarted => "game_started",
+            Self::PlayerExists(_) => "player_exists",
+            Self::PlayerNotFound(_) => "player_not_found",
+            Self::PlayerConnected(_) => "player_connected",
+            Self::PlayerDisconnected(_) => "player_disconnected",
+            Self::IncorrectPassword => "incorrect_password",
+            Self::NotHost => "not_host",
+            Self::InvalidMessage => "invalid_message",
+            Self::WrongPhase => "wrong_phase",
+            Self::BidNotLower(_) => "bid_not_lower",
+            Self::AlreadyPassed(_) => "already_passed",
+            Self::NotSolver => "not_solver",
+            Self::PlayersNotReady => "players_not_ready",
+            Self::RoomFull => "room_full",
+            Self::HostCannotLeave => "host_cannot_leave",
+            Self::InvalidSetting(_) => "invalid_setting",
+            Self::RateLimited => "rate_limited",
+            Self::InvalidText(_) => "invalid_text",
+            Self::NothingToUndo => "nothing_to_undo",
+            Self::ColorTaken => "color_taken",
+            Self::GameNotOver => "game_not_over",
+            Self::ChatNotFound(_) => "chat_not_found",
+            Self::NotAuthor => "not_author",
+            Self::RoundNotFound(_) => "round_not_found",
+        }
+    }
+}
+
+/// bumped whenever a change to `PlayerMessage` or `ServerMessage` would break existing clients.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+const DEFAULT_ROUNDS: u32 = 17;
+const DEFAULT_BID_SECONDS: u64 = 60;
+const DEFAULT_SOLVE_SECONDS: u64 = 60;
+const DEFAULT_MAX_PLAYERS: usize = 8;
+const MAX_PLAYERS_RANGE: RangeInclusive<usize> = 2..=16;
+// how long bidding and each solving attempt can be set to last
+const PHASE_SECONDS_RANGE: RangeInclusive<u64> = 5..=300;
+// far more than any target needs, so anything above it is a broken or hostile client
+const MAX_BID: u32 = 99;
+// chat and emotes share an allowance of this many messages, refilled at the given rate
+const CHAT_BURST: f64 = 5.0;
+const CHAT_PER_SECOND: f64 = 1.0;
+// typing changes closer together than this are held back and sent on the next tick
+const TYPING_DEBOUNCE: Duration = Duration::from_secs(1);
+// how long the robots have to sit still before a `BoardState` goes out for a burst of moves
+const BOARD_STATE_DEBOUNCE: Duration = Duration::from_millis(500);
+// how many sent messages each room remembers for debugging
+const LOG_CAPACITY: usize = 256;
+// how many recent chats can still be edited or deleted, or caught up on with `since`
+const CHAT_MEMORY: usize = 256;
+// how many recent chats a player is caught up on when they connect without `since`
+const CHAT_REPLAY: usize = 50;
+// how many of the latest rounds each room keeps the attempts at, for `request_history`
+const HISTORY_ROUNDS: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RoomSettings {
+    pub rounds: u32,
+    pub bid_seconds: u64,
+    pub solve_seconds: u64,
+    pub max_players: usize,
+    /// starts the game as soon as the room is full and everyone but the host is ready.
+    pub auto_start_when_full: bool,
+    /// who solves first out of players who bid the same number of moves.
+    pub tie_break: TieBreak,
+    /// ends the game as soon as someone has this many points, even partway through the rounds.
+    pub target_score: Option<i32>,
+    /// points a solver loses for running out of moves or time, or giving up.
+    pub fail_penalty: i32,
+    /// how low the fail penalty can take anyone's points.
+    pub points_floor: i32,
+    /// keeps everyone's points to the server until the game is over.
+    pub hide_scores_until_end: bool,
+    /// leaves the room out of `/rooms`, so it can only be joined by code.
+    pub private: bool,
+    /// cells along each side of the board, one of `BOARD_SIZES`.
+    pub board_size: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    /// whoever bid first.
+    #[default]
+    Earliest,
+    /// a shuffle, drawn from the room's seed so it can be replayed.
+    Random,
+    /// whoever has the most points, then whoever bid first. not allowed with hidden scores.
+    Score,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        Self {
+            rounds: DEFAULT_ROUNDS,
+            bid_seconds: DEFAULT_BID_SECONDS,
+            solve_seconds: DEFAULT_SOLVE_SECONDS,
+            max_players: DEFAULT_MAX_PLAYERS,
+            auto_start_when_full: false,
+            tie_break: TieBreak::default(),
+            target_score: None,
+            fail_penalty: 0,
+            points_floor: 0,
+            hide_scores_until_end: false,
+            private: false,
+            board_size: BOARD_SIZE,
+        }
+    }
+}
+
+impl RoomSettings {
+    pub fn validate(&self) -> Result<(), RoomError> {
+        if !MAX_PLAYERS_RANGE.contains(&self.max_players) {
+            Err(RoomError::InvalidSetting("max_players"))
+        } else if !PHASE_SECONDS_RANGE.contains(&self.bid_seconds) {
+            Err(RoomError::InvalidSetting("bid_seconds"))
+        } else if !PHASE_SECONDS_RANGE.contains(&self.solve_seconds) {
+            Err(RoomError::InvalidSetting("solve_seconds"))
+        } else if self.target_score.is_some_and(|score| score <= 0) {
+            Err(RoomError::InvalidSetting("target_score"))
+        } else if self.fail_penalty < 0 {
+            Err(RoomError::InvalidSetting("fail_penalty"))
+        } else if !BOARD_SIZES.contains(&self.board_size) {
+            Err(RoomError::InvalidSetting("board_size"))
+        } else if self.hide_scores_until_end && self.tie_break == TieBreak::Score {
+            // the solving order would give the hidden scores away
+            Err(RoomError::InvalidSetting("tie_break"))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Room {
-    tokens: HashMap<[u8; TOKEN_LEN], Arc<str>>,
-    password: Option<Arc<str>>,
+    code: Arc<str>,
+    // keyed by the hash of each token so live tokens never sit in room state
+    tokens: HashMap<TokenHash, Arc<str>>,
+    password: Option<Password>,
     players: HashMap<Arc<str>, Player>,
     host: Arc<str>,
     phase: Option<Phase>,
-    websocket_channel: Sender<(Arc<str>, PlayerMessage)>,
+    settings: RoomSettings,
+    // everything random about the game is derived from this, so it can be replayed
+    seed: u64,
+    board: Board,
+    // the board's targets in the order they come up, one per round
+    pile: Vec<Target>,
+    spectators: broadcast::Sender<Arc<ServerMessage>>,
+    // spectators who gave a name, by connection. shared with each `Watching` so leaving doesn't
+    // need the room's lock
+    watchers: Watchers,
+    // counts every connection ever made, to tell a player's connections apart
+    connections: u64,
+    options: RoomOptions,
+    // the most recently sent messages, oldest first
+    log: VecDeque<LogEntry>,
+    // when the game ended, so the room can be cleared out a while later
+    finished: Option<Instant>,
+    // when the room was created, until its host first connects. restored rooms aren't waited on
+    awaiting_host: Option<Instant>,
+    // when a robot last moved, while a `BoardState` for it is still to be sent
+    board_changed: Option<Instant>,
+    // the fewest moves the current round's target takes, being searched for off the runtime
+    optimum: Option<JoinHandle<Option<u32>>>,
+    // the most recent chats, oldest first
+    chats: VecDeque<ChatEntry>,
+    next_chat_id: u64,
+    // the attempts at the latest rounds, oldest first
+    history: VecDeque<RoundHistory>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// a message the room sent, for piecing together what happened after the fact.
+#[derive(Serialize, Debug, Clone)]
+pub struct LogEntry {
+    // milliseconds since the unix epoch
+    at: u64,
+    // `None` for messages sent to everyone
+    to: Option<Arc<str>>,
+    message: Arc<ServerMessage>,
+    // how far a message sent to everyone got
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delivery: Option<Delivery>,
+}
+
+/// who a message sent to everyone reached.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Delivery {
+    pub delivered: usize,
+    /// players left out for not being connected.
+    pub skipped_disconnected: usize,
+    /// players whose connection turned out to be gone, and who were disconnected for it.
+    pub failed: usize,
+}
+
+/// what the server opens every room with, as opposed to the settings the host picks.
+#[derive(Debug, Clone)]
+pub struct RoomOptions {
+    pub token_len: usize,
+    pub channel_capacity: usize,
+    pub filter: Arc<WordFilter>,
+    pub counters: Arc<Counters>,
+}
+
+/// running totals across every room since the server started.
+#[derive(Debug, Default)]
+pub struct Counters {
+    pub rooms_created: AtomicUsize,
+    pub players_joined: AtomicUsize,
+    pub games_started: AtomicUsize,
+    pub games_finished: AtomicUsize,
+}
+
+impl Counters {
+    fn count(counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+type TokenHash = [u8; 32];
+
+/// the persistent parts of a room, without any live connections.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomSnapshot {
+    code: Arc<str>,
+    tokens: Vec<(TokenHash, Arc<str>)>,
+    password: Option<Password>,
+    players: Vec<PlayerSnapshot>,
+    host: Arc<str>,
+    phase: Option<Phase>,
+    settings: RoomSettings,
+    seed: u64,
+    board: Board,
+    // so chat ids keep counting up across a restart
+    #[serde(default)]
+    next_chat_id: u64,
+}
+
+impl RoomSnapshot {
+    pub fn code(&self) -> Arc<str> {
+        self.code.clone()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PlayerSnapshot {
+    username: Arc<str>,
+    points: i32,
+    #[serde(default)]
+    color: Option<Color>,
+    #[serde(default)]
+    robots: Vec<Robot>,
+}
+
+/// searches for the fewest moves to `target` on a blocking thread, since on a big board it can take
+/// long enough to hold up everything else on the runtime.
+fn solve_in_background(board: Board, target: Target) -> JoinHandle<Option<u32>> {
+    task::spawn_blocking(move || board.min_moves(target))
+}
+
+/// shuffles the board's targets, so a game visits each at most once and in an order fixed by the seed.
+fn draw_pile(board: &Board, seed: u64) -> Vec<Target> {
+    let mut pile = board.targets().to_vec();
+    // the board was generated from the seed itself, so start a fresh stream for the shuffle
+    pile.shuffle(&mut StdRng::seed_from_u64(seed.rotate_left(32)));
+    pile
 }
 
-fn generate_token() -> [u8; TOKEN_LEN] {
-    let mut token = [0; TOKEN_LEN];
-    rng().fill_bytes(&mut token);
-    token
+fn hash_token(token: &[u8]) -> TokenHash {
+    Sha256::digest(token).into()
 }
 
 impl Room {
-    pub fn create(host: Arc<str>, password: Option<Arc<str>>) -> (Self, [u8; TOKEN_LEN]) {
+    pub fn create(
+        code: Arc<str>,
+        host: Username,
+        password: Option<Password>,
+        settings: RoomSettings,
+        seed: u64,
+        options: RoomOptions,
+    ) -> (Self, Token) {
+        let host: Arc<str> = host.into();
+        let board = Board::generate(settings.board_size, &mut StdRng::seed_from_u64(seed));
         let mut room = Self {
+            code,
             tokens: HashMap::new(),
             password,
             players: HashMap::new(),
             host: host.clone(),
             phase: None,
+            settings,
+            seed,
+            pile: draw_pile(&board, seed),
+            board,
+            spectators: broadcast::Sender::new(options.channel_capacity),
+            watchers: Watchers::default(),
+            connections: 0,
+            options,
+            log: VecDeque::with_capacity(LOG_CAPACITY),
+            finished: None,
+            awaiting_host: Some(Instant::now()),
+            board_changed: None,
+            optimum: None,
+            chats: VecDeque::new(),
+            next_chat_id: 0,
+            history: VecDeque::new(),
         };
 
         room.players.insert(host.clone(), Player::default());
         let token = room.create_token(host);
-
-        tokio::spawn(|| async {
-            
-        })
+        Counters::count(&room.options.counters.rooms_created);
 
         (room, token)
     }
 
-    fn create_token(&mut self, username: Arc<str>) -> [u8; TOKEN_LEN] {
-        let mut token = generate_token();
-        while self.tokens.contains_key(&token) {
-            token = generate_token();
+    pub fn restore(snapshot: RoomSnapshot, options: RoomOptions) -> Self {
+        // the clock restarts for games that had already ended
+        let finished = matches!(snapshot.phase, Some(Phase::GameOver)).then(Instant::now);
+        Self {
+            code: snapshot.code,
+            tokens: snapshot.tokens.into_iter().collect(),
+            password: snapshot.password,
+            players: snapshot
+                .players
+                .into_iter()
+                .map(|player| {
+                    (
+                        player.username,
+                        Player {
+                            points: player.points,
+                            color: player.color,
+                            robots: player.robots,
+                            ..Player::default()
+                        },
+                    )
+                })
+                .collect(),
+            host: snapshot.host,
+            phase: snapshot.phase,
+            settings: snapshot.settings,
+            seed: snapshot.seed,
+            pile: draw_pile(&snapshot.board, snapshot.seed),
+            board: snapshot.board,
+            spectators: broadcast::Sender::new(options.channel_capacity),
+            watchers: Watchers::default(),
+            connections: 0,
+            options,
+            log: VecDeque::with_capacity(LOG_CAPACITY),
+            finished,
+            awaiting_host: None,
+            board_changed: None,
+            optimum: None,
+            chats: VecDeque::new(),
+            next_chat_id: snapshot.next_chat_id,
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot {
+            code: self.code.clone(),
+            tokens: self
+                .tokens
+                .iter()
+                .map(|(hash, username)| (*hash, username.clone()))
+                .collect(),
+            password: self.password.clone(),
+            players: self
+                .players
+                .iter()
+                .map(|(username, player)| PlayerSnapshot {
+                    username: username.clone(),
+                    points: player.points,
+                    color: player.color,
+                    robots: player.robots.clone(),
+                })
+                .collect(),
+            host: self.host.clone(),
+            phase: self.phase.clone(),
+            settings: self.settings.clone(),
+            seed: self.seed,
+            board: self.board.clone(),
+            next_chat_id: self.next_chat_id,
+        }
+    }
+
+    /// picks an anonymous username that isn't already taken in this room.
+    pub fn anonymous_username(&self) -> Username {
+        let mut username = Username::anonymous();
+        while self.is_taken(username.as_str()) {
+            username = Username::anonymous();
+        }
+        username
+    }
+
+    /// whether a player already goes by `username`, ignoring case.
+    fn is_taken(&self, username: &str) -> bool {
+        self.find_player(username).is_some()
+    }
+
+    /// the name of the player who goes by `username`, ignoring case.
+    fn find_player(&self, username: &str) -> Option<Arc<str>> {
+        let folded = fold_username(username);
+        self.players
+            .keys()
+            .find(|player| fold_username(player) == folded)
+            .cloned()
+    }
+
+    fn create_token(&mut self, username: Arc<str>) -> Token {
+        let mut token = Token::generate(self.options.token_len);
+        while self.tokens.contains_key(&hash_token(token.expose())) {
+            token = Token::generate(self.options.token_len);
         }
-        self.tokens.insert(token, username);
+        self.tokens.insert(hash_token(token.expose()), username);
         token
     }
 
+    #[tracing::instrument(name = "room", skip_all, fields(code = %self.code, user = %username))]
     pub async fn handle_message(&mut self, username: Arc<str>, message: PlayerMessage) {
-        match message {
-            PlayerMessage::Chat { text } => {
-                self.send_all(Arc::new(ServerMessage::Chat { username, text }))
+        let result = match message {
+            PlayerMessage::Ping { nonce } => {
+                self.deliver(username.clone(), Arc::new(ServerMessage::Pong { nonce }))
+                    .await
+            }
+            PlayerMessage::Chat { text } => self.chat(username.clone(), text).await,
+            PlayerMessage::ChatEdit { id, text } => {
+                self.edit_chat(username.clone(), id, text).await
+            }
+            PlayerMessage::ChatDelete { id } => self.delete_chat(username.clone(), id).await,
+            PlayerMessage::Emote { kind } => self.emote(username.clone(), kind).await,
+            PlayerMessage::Whisper { to, text } => self.whisper(username.clone(), to, text).await,
+            PlayerMessage::Typing { active } => self.set_typing(username.clone(), active).await,
+            PlayerMessage::Start { force } => self.start(username.clone(), force).await,
+            PlayerMessage::SetReady { ready } => self.set_ready(username.clone(), ready).await,
+            PlayerMessage::SetColor { color } => self.set_color(username.clone(), color).await,
+            PlayerMessage::Bid { moves } => self.bid(username.clone(), moves).await,
+            PlayerMessage::Move { robot, direction } => {
+                self.move_robot(username.clone(), robot, direction).await
+            }
+            PlayerMessage::Kick { username: target } => self.kick(username.clone(), target).await,
+            PlayerMessage::AssignRobot {
+                username: target,
+                robot,
+            } => self.assign_robot(username.clone(), target, robot).await,
+            PlayerMessage::TransferHost { to } => {
+                self.transfer_host(username.clone(), to.into()).await
+            }
+            PlayerMessage::Undo => self.undo(username.clone()).await,
+            PlayerMessage::Leave => self.leave(username.clone()).await,
+            PlayerMessage::Pass => self.pass(username.clone()).await,
+            PlayerMessage::RequestHistory { round } => {
+                self.send_history(username.clone(), round).await
+            }
+            PlayerMessage::Resync => {
+                self.send_one(username.clone(), Arc::new(self.welcome(username.clone())))
                     .await
             }
-            PlayerMessage::Start => unimplemented!(),
+            PlayerMessage::AdjustPoints {
+                username: target,
+                delta,
+            } => {
+                if username != self.host {
+                    Err(RoomError::NotHost)
+                } else {
+                    self.award(target, delta).await
+                }
+            }
         };
+
+        if let Err(err) = result {
+            self.reject(username, err).await;
+        }
     }
 
-    pub async fn connect(
+    /// reports an error back to the player whose action caused it, without notifying anyone else.
+    pub async fn reject(&mut self, username: Arc<str>, err: RoomError) {
+        tracing::info!("rejecting action from {username}: {err}");
+        let _ = self
+            .send_one(
+                username,
+                Arc::new(ServerMessage::Error {
+                    code: err.code().into(),
+                    message: err.to_string(),
+                }),
+            )
+            .await;
+    }
+
+    async fn chat(&mut self, username: Arc<str>, text: Arc<str>) -> Result<(), RoomError> {
+        let text = ChatText::validate(text)
+            .map_err(RoomError::InvalidText)?
+            .masked(&self.options.filter);
+        self.throttle(&username)?;
+
+        let id = self.next_chat_id;
+        self.next_chat_id += 1;
+        if self.chats.len() == CHAT_MEMORY {
+            self.chats.pop_front();
+        }
+        let text: Arc<str> = text.into();
+        self.chats.push_back(ChatEntry {
+            id,
+            username: username.clone(),
+            text: text.clone(),
+        });
+        self.send_all(Arc::new(ServerMessage::Chat { id, username, text }))
+            .await;
+        Ok(())
+    }
+
+    async fn edit_chat(
         &mut self,
         username: Arc<str>,
-    ) -> Result<Receiver<Arc<ServerMessage>>, RoomError> {
-        tracing::info!("player {username} connecting");
-        let channel_handle = &mut self
-            .players
-            .get_mut(&username)
-            .ok_or(RoomError::PlayerNotFound(username.clone()))?
-            .channel_handle;
+        id: u64,
+        text: Arc<str>,
+    ) -> Result<(), RoomError> {
+        let text = ChatText::validate(text)
+            .map_err(RoomError::InvalidText)?
+            .masked(&self.options.filter);
+        let index = self.own_chat(&username, id)?;
+        self.throttle(&username)?;
+        let text: Arc<str> = text.into();
+        self.chats[index].text = text.clone();
+        self.send_all(Arc::new(ServerMessage::ChatEdited { id, text }))
+            .await;
+        Ok(())
+    }
 
-        if channel_handle.is_some() {
-            tracing::warn!("player {username} tried to connect while connected");
-            Err(RoomError::PlayerConnected(username))
+    async fn delete_chat(&mut self, username: Arc<str>, id: u64) -> Result<(), RoomError> {
+        let index = self.own_chat(&username, id)?;
+        self.chats.remove(index);
+        self.send_all(Arc::new(ServerMessage::ChatDeleted { id }))
+            .await;
+        Ok(())
+    }
+
+    /// where chat `id` is in `chats`, as long as `username` sent it.
+    fn own_chat(&self, username: &Arc<str>, id: u64) -> Result<usize, RoomError> {
+        let index = self
+            .chats
+            .iter()
+            .position(|chat| chat.id == id)
+            .ok_or(RoomError::ChatNotFound(id))?;
+        if self.chats[index].username == *username {
+            Ok(index)
         } else {
-            let (sender, receiver) = mpsc::channel::<Arc<ServerMessage>>(CHANNEL_CAPACITY);
-            *channel_handle = Some(sender);
-
-            let _ = self
-                .send_one(
-                    username.clone(),
-                    Arc::new(ServerMessage::Welcome {
-                        username: username.clone(),
-                        players: self
-                            .players
-                            .iter()
-                            .map(|(n, p)| PlayerDescriptor {
-                                username: n.clone(),
-                                points: p.points,
-                            })
-                            .collect(),
-                        host: self.host.clone(),
-                        phase: self.phase.clone(),
-                    }),
-                )
-                .await;
-            self.send_all(Arc::new(ServerMessage::Connect { username }))
-                .await;
-            Ok(receiver)
+            Err(RoomError::NotAuthor)
         }
     }
 
-    pub async fn disconnect(&mut self, username: Arc<str>) -> Result<(), RoomError> {
-        tracing::info!("player {username} disconnecting");
+    /// sends a message to one connected player, and a copy back to the sender.
+    async fn whisper(
+        &mut self,
+        from: Arc<str>,
+        to: Username,
+        text: Arc<str>,
+    ) -> Result<(), RoomError> {
+        let text = ChatText::validate(text)
+            .map_err(RoomError::InvalidText)?
+            .masked(&self.options.filter);
+        let to: Arc<str> = to.into();
+        self.throttle(&from)?;
 
-        self.players
+        let message = Arc::new(ServerMessage::Whisper {
+            from: from.clone(),
+            to: to.clone(),
+            text: text.into(),
+        });
+        // delivered without logging, to keep the conversation private
+        self.deliver(to.clone(), message.clone()).await?;
+        if to != from {
+            self.deliver(from, message).await?;
+        }
+        Ok(())
+    }
+
+    async fn emote(&mut self, username: Arc<str>, kind: Emote) -> Result<(), RoomError> {
+        self.throttle(&username)?;
+        self.send_all(Arc::new(ServerMessage::Emote { username, kind }))
+            .await;
+        Ok(())
+    }
+
+    async fn set_typing(&mut self, username: Arc<str>, active: bool) -> Result<(), RoomError> {
+        let typing = &mut self
+            .players
             .get_mut(&username)
             .ok_or(RoomError::PlayerNotFound(username.clone()))?
-            .channel_handle
-            .take()
-            .ok_or(RoomError::PlayerDisconnected(username.clone()))?;
+            .typing;
+        typing.wanted = active;
 
-        self.send_all(Arc::new(ServerMessage::Disconnect { username }))
-            .await;
+        if typing.settled() {
+            self.show_typing(username).await;
+        }
         Ok(())
     }
 
-    pub fn authenticate<T>(&self, token: T) -> Option<Arc<str>>
-    where
-        T: AsRef<[u8]>,
-    {
-        self.tokens.get(token.as_ref()).cloned()
+    /// tells everyone else whether the player is typing, if that's changed since they were last
+    /// told.
+    async fn show_typing(&mut self, username: Arc<str>) {
+        let Some(player) = self.players.get_mut(&username) else {
+            return;
+        };
+        let typing = &mut player.typing;
+        if typing.wanted == typing.shown {
+            return;
+        }
+        typing.shown = typing.wanted;
+        typing.changed = Some(Instant::now());
+
+        let active = typing.shown;
+        self.broadcast_except(
+            &username,
+            Arc::new(ServerMessage::Typing {
+                username: username.clone(),
+                active,
+            }),
+        )
+        .await;
+    }
+
+    /// sends any typing changes that were held back by the debounce.
+    async fn flush_typing(&mut self) {
+        let pending: Vec<_> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.typing.wanted != player.typing.shown)
+            .filter(|(_, player)| player.typing.settled())
+            .map(|(username, _)| username.clone())
+            .collect();
+        for username in pending {
+            self.show_typing(username).await;
+        }
+    }
+
+    /// sends where the robots are once they've settled after a burst of moves, or straight away
+    /// with `force`, e.g. before the board is reset for the next attempt.
+    async fn flush_board_state(&mut self, force: bool) {
+        let Some(changed) = self.board_changed else {
+            return;
+        };
+        if !force && changed.elapsed() < BOARD_STATE_DEBOUNCE {
+            return;
+        }
+        self.board_changed = None;
+        self.broadcast(Arc::new(ServerMessage::BoardState {
+            robots: self.board.robots(),
+        }))
+        .await;
     }
 
-    pub async fn join(
+    /// spends one of the player's chat allowance, failing if they've used it all up.
+    fn throttle(&mut self, username: &Arc<str>) -> Result<(), RoomError> {
+        let player = self
+            .players
+            .get_mut(username)
+            .ok_or(RoomError::PlayerNotFound(username.clone()))?;
+        if player.chat_limiter.try_acquire() {
+            Ok(())
+        } else {
+            Err(RoomError::RateLimited)
+        }
+    }
+
+    /// takes a finished game back to the lobby for a rematch, keeping the players and their
+    /// tokens. the board is dealt again from `seed`, or from the last one without it.
+    pub async fn reset(&mut self, username: Arc<str>, seed: Option<u64>) -> Result<(), RoomError> {
+        if username != self.host {
+            return Err(RoomError::NotHost);
+        }
+        if !matches!(self.phase, Some(Phase::GameOver)) {
+            return Err(RoomError::GameNotOver);
+        }
+
+        if let Some(seed) = seed {
+            self.seed = seed;
+        }
+        self.board = Board::generate(
+            self.settings.board_size,
+            &mut StdRng::seed_from_u64(self.seed),
+        );
+        self.pile = draw_pile(&self.board, self.seed);
+        self.phase = None;
+        self.finished = None;
+        self.history.clear();
+        for player in self.players.values_mut() {
+            player.points = 0;
+            player.ready = false;
+        }
+        tracing::info!("game reset");
+        self.send_all(Arc::new(ServerMessage::GameReset {
+            players: self.descriptors(),
+            board: self.board.clone(),
+        }))
+        .await;
+        Ok(())
+    }
+
+    /// lets the host change the room's settings until the game starts. the board is dealt again
+    /// from the same seed if its size changed.
+    pub async fn configure(
         &mut self,
         username: Arc<str>,
-        password: Option<Arc<str>>,
-    ) -> Result<[u8; TOKEN_LEN], RoomError> {
+        settings: RoomSettings,
+    ) -> Result<(), RoomError> {
+        if username != self.host {
+            return Err(RoomError::NotHost);
+        }
         if self.phase.is_some() {
+            return Err(RoomError::GameStarted);
+        }
+        settings.validate()?;
+        // nobody already in is turned out to make room
+        if settings.max_players < self.players.len() {
+            return Err(RoomError::InvalidSetting("max_players"));
+        }
+
+        if settings.board_size != self.settings.board_size {
+            self.board =
+                Board::generate(settings.board_size, &mut StdRng::seed_from_u64(self.seed));
+            self.pile = draw_pile(&self.board, self.seed);
+        }
+        self.settings = settings;
+        tracing::info!("settings changed");
+        self.send_all(Arc::new(ServerMessage::SettingsChanged {
+            settings: self.settings.clone(),
+            board: self.board.clone(),
+        }))
+        .await;
+        self.auto_start().await;
+        Ok(())
+    }
+
+    /// starts the game once every connected player is ready, or straight away if `force` is set.
+    pub async fn start(&mut self, username: Arc<str>, force: bool) -> Result<(), RoomError> {
+        if username != self.host {
+            Err(RoomError::NotHost)
+        } else if self.phase.is_some() {
             Err(RoomError::GameStarted)
-        } else if self.players.contains_key(&username) {
-            Err(RoomError::PlayerExists(username))
-        } else if self.password != password {
-            Err(RoomError::IncorrectPassword)
+        } else if !force && !self.everyone_ready() {
+            Err(RoomError::PlayersNotReady)
         } else {
-            self.players.insert(username.clone(), Player::default());
+            self.begin_round(1).await;
+            Ok(())
+        }
+    }
 
-            self.send_all(Arc::new(ServerMessage::Join {
-                username: username.clone(),
-            }))
-            .await;
-            Ok(self.create_token(username))
+    // the host doesn't need to be marked ready, since starting the game says as much
+    fn everyone_ready(&self) -> bool {
+        self.players.iter().all(|(username, player)| {
+            player.ready || player.channel_handle.is_none() || *username == self.host
+        })
+    }
+
+    // unlike a manual start, players who haven't connected yet have to be ready too, since the
+    // host isn't around to judge whether to wait for them
+    async fn auto_start(&mut self) {
+        let full = self.players.len() >= self.settings.max_players;
+        if self.settings.auto_start_when_full
+            && self.phase.is_none()
+            && full
+            && self
+                .players
+                .iter()
+                .all(|(username, player)| player.ready || *username == self.host)
+        {
+            tracing::info!("room full and ready, starting");
+            self.begin_round(1).await;
         }
     }
 
-    pub async fn leave(&mut self, username: Arc<str>) -> Result<(), RoomError> {
-        todo!();
+    /// picks the color a player shows up in. no two players can share one.
+    async fn set_color(&mut self, username: Arc<str>, color: Color) -> Result<(), RoomError> {
+        if self
+            .players
+            .iter()
+            .any(|(other, player)| *other != username && player.color == Some(color))
+        {
+            return Err(RoomError::ColorTaken);
+        }
+        self.players
+            .get_mut(&username)
+            .ok_or(RoomError::PlayerNotFound(username.clone()))?
+            .color = Some(color);
 
-        //self.players
-        //    .remove(&username)
-        //    .ok_or(RoomError::PlayerNotFound(username.clone()))?;
-        //
-        //self.tokens.remove_by_right(&username);
-        //
-        //self.send_all(ServerMessage::Leave { username }).await;
-        //Ok(())
+        self.send_all(Arc::new(ServerMessage::ColorChanged {
+            username: username.clone(),
+            color,
+        }))
+        .await;
+        self.update_player(&username).await;
+        Ok(())
     }
 
-    async fn send_one(
+    /// lets the host make a player responsible for a robot, taking it off whoever had it. once a
+    /// player has any robots they can only move those. `None` leaves the robot to nobody.
+    async fn assign_robot(
         &mut self,
-        recipient: Arc<str>,
-        message: Arc<ServerMessage>,
+        username: Arc<str>,
+        target: Option<Arc<str>>,
+        robot: Robot,
     ) -> Result<(), RoomError> {
-        tracing::info!("sending message {message:?} to {recipient}");
-        let _ = self
+        if username != self.host {
+            return Err(RoomError::NotHost);
+        }
+        if let Some(target) = &target
+            && !self.players.contains_key(target)
+        {
+            return Err(RoomError::PlayerNotFound(target.clone()));
+        }
+
+        let previous = self
             .players
-            .get_mut(&recipient)
-            .ok_or(RoomError::PlayerNotFound(recipient.clone()))?
-            .channel_handle
-            .as_mut()
-            .ok_or(RoomError::PlayerDisconnected(recipient.clone()))?
-            .send(message.clone())
-            .await;
+            .iter_mut()
+            .find(|(_, player)| player.robots.contains(&robot))
+            .map(|(owner, player)| {
+                player.robots.retain(|&assigned| assigned != robot);
+                owner.clone()
+            });
+        if let Some(player) = target
+            .as_ref()
+            .and_then(|target| self.players.get_mut(target))
+        {
+            player.robots.push(robot);
+            player.robots.sort();
+        }
+
+        self.send_all(Arc::new(ServerMessage::RobotAssigned {
+            robot,
+            username: target.clone(),
+        }))
+        .await;
+        for username in previous.iter().chain(&target) {
+            self.update_player(username).await;
+        }
         Ok(())
     }
 
-    async fn send_all(&mut self, message: Arc<ServerMessage>) {
-        tracing::info!("sending message {message:?} to all");
-        join_all(
-            self.players
-                .values_mut()
-                .filter_map(|player| Some(player.channel_handle.as_mut()?.send(message.clone()))),
-        )
+    async fn set_ready(&mut self, username: Arc<str>, ready: bool) -> Result<(), RoomError> {
+        if self.phase.is_some() {
+            return Err(RoomError::GameStarted);
+        }
+        self.players
+            .get_mut(&username)
+            .ok_or(RoomError::PlayerNotFound(username.clone()))?
+            .ready = ready;
+
+        self.send_all(Arc::new(ServerMessage::ReadyChanged {
+            username: username.clone(),
+            ready,
+        }))
         .await;
+        self.update_player(&username).await;
+        self.auto_start().await;
+        Ok(())
     }
-}
 
-#[derive(Debug)]
-struct Player {
-    points: i32,
-    channel_handle: Option<Sender<Arc<ServerMessage>>>,
-}
+    /// declares the game over after `rounds` rounds, with the final standings.
+    async fn end_game(&mut self, rounds: u32) {
+        self.flush_board_state(true).await;
+        self.finished = Some(Instant::now());
+        Counters::count(&self.options.counters.games_finished);
+        self.set_phase(Phase::GameOver).await;
+        let standings = self.standings();
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PlayerDescriptor {
-    username: Arc<str>,
-    points: i32,
-}
+        // one line per game for analytics, apart from the gameplay logs
+        tracing::info!(
+            target: "game.outcome",
+            code = %self.code,
+            seed = self.seed,
+            rounds,
+            players = self.players.len(),
+            standings = %serde_json::to_string(&standings).expect("serializing standings failed"),
+            "game over"
+        );
+        self.send_all(Arc::new(ServerMessage::GameOver {
+            standings,
+            seed: self.seed,
+        }))
+        .await;
+    }
 
-impl Default for Player {
-    fn default() -> Self {
-        Player {
-            points: 0,
-            channel_handle: None,
+    /// whether anyone has reached the room's target score, if it has one.
+    fn target_score_reached(&self) -> bool {
+        self.settings
+            .target_score
+            .is_some_and(|target| self.players.values().any(|player| player.points >= target))
+    }
+
+    /// opens bidding on the next target, or ends the game once every round has been played.
+    async fn begin_round(&mut self, round: u32) {
+        // the game ends after the set number of rounds, or sooner if the pile runs out
+        let target = self.pile.get(round as usize - 1).copied();
+        let Some(target) = target.filter(|_| round <= self.settings.rounds) else {
+            return self.end_game(round - 1).await;
+        };
+        if self.target_score_reached() {
+            return self.end_game(round - 1).await;
         }
+        if round == 1 {
+            Counters::count(&self.options.counters.games_started);
+        }
+        // the robots stay put while bidding, so this is where the round will be solved from
+        self.optimum = Some(solve_in_background(self.board.clone(), target));
+        if self.history.len() == HISTORY_ROUNDS {
+            self.history.pop_front();
+        }
+        self.history.push_back(RoundHistory {
+            round,
+            target,
+            attempts: Vec::new(),
+        });
+        self.set_phase(Phase::Bidding {
+            round,
+            target,
+            bids: Vec::new(),
+            passed: Vec::new(),
+            deadline: Instant::now() + Duration::from_secs(self.settings.bid_seconds),
+        })
+        .await;
     }
-}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum Phase {
-    Bidding,
-}
+    async fn set_phase(&mut self, phase: Phase) {
+        self.phase = Some(phase.clone());
+        let seconds = self.phase_seconds(&phase);
+        self.send_all(Arc::new(ServerMessage::PhaseChanged { phase, seconds }))
+            .await;
+    }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum PlayerMessage {
-    Chat { text: Arc<str> },
-    Start,
-}
+    /// how long the phase lasts from its start, if it's timed at all.
+    fn phase_seconds(&self, phase: &Phase) -> Option<u64> {
+        match phase {
+            Phase::Bidding { .. } => Some(self.settings.bid_seconds),
+            Phase::Solving { .. } => Some(self.settings.solve_seconds),
+            Phase::GameOver => None,
+        }
+    }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum ServerMessage {
-    Join {
-        username: Arc<str>,
-    },
-    Leave {
-        username: Arc<str>,
-    },
-    Connect {
+    async fn bid(&mut self, username: Arc<str>, moves: u32) -> Result<(), RoomError> {
+        let Some(Phase::Bidding { bids, passed, .. }) = &mut self.phase else {
+            return Err(RoomError::WrongPhase);
+        };
+        if passed.contains(&username) {
+            return Err(RoomError::AlreadyPassed(username));
+        }
+        let rejection = match moves {
+            1..=MAX_BID => None,
+            0 => Some(BidRejection::TooLow),
+            _ => Some(BidRejection::TooHigh),
+        };
+        if let Some(reason) = rejection {
+            return self
+                .deliver(username, Arc::new(ServerMessage::BidRejected { reason }))
+                .await;
+        }
+        // a player can go lower, which counts as bidding afresh when breaking ties
+        if let Some(bid) = bids.iter_mut().find(|bid| bid.username == username) {
+            if moves >= bid.moves {
+                return Err(RoomError::BidNotLower(bid.moves));
+            }
+            bid.moves = moves;
+            bid.at = now_millis();
+        } else {
+            bids.push(Bid {
+                username: username.clone(),
+                moves,
+                at: now_millis(),
+            });
+        }
+
+        self.send_all(Arc::new(ServerMessage::BidPlaced { username, moves }))
+            .await;
+        if self.everyone_bid() {
+            self.end_bidding().await;
+        }
+        Ok(())
+    }
+
+    fn everyone_bid(&self) -> bool {
+        let Some(Phase::Bidding { bids, passed, .. }) = &self.phase else {
+            return false;
+        };
+        self.players
+            .iter()
+            .filter(|(_, player)| player.channel_handle.is_some())
+            .all(|(username, _)| {
+                passed.contains(username) || bids.iter().any(|bid| &bid.username == username)
+            })
+    }
+
+    /// gives up on the current target. while bidding this withdraws any bid the player made;
+    /// while solving it forfeits the player's attempt, or their place in the queue.
+    async fn pass(&mut self, username: Arc<str>) -> Result<(), RoomError> {
+        match &mut self.phase {
+            Some(Phase::Bidding { bids, passed, .. }) => {
+                if passed.contains(&username) {
+                    return Err(RoomError::AlreadyPassed(username));
+                }
+                bids.retain(|bid| bid.username != username);
+                passed.push(username.clone());
+            }
+            Some(Phase::Solving { solver, queue, .. }) => {
+                if *solver != username {
+                    let len = queue.len();
+                    queue.retain(|bid| bid.username != username);
+                    if queue.len() == len {
+                        return Err(RoomError::NotSolver);
+                    }
+                }
+            }
+            _ => return Err(RoomError::WrongPhase),
+        }
+
+        self.send_all(Arc::new(ServerMessage::Passed {
+            username: username.clone(),
+        }))
+        .await;
+        match &self.phase {
+            Some(Phase::Bidding { .. }) if self.everyone_bid() => self.end_bidding().await,
+            Some(Phase::Solving { solver, .. }) if *solver == username => self.fail_attempt().await,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// closes bidding and hands the board to the lowest bidder, or skips the target if nobody bid.
+    async fn end_bidding(&mut self) {
+        let Some(Phase::Bidding {
+            round,
+            target,
+            mut bids,
+            ..
+        }) = self.phase.clone()
+        else {
+            return;
+        };
+
+        if bids.is_empty() {
+            self.end_round(round, target, self.board.robots(), None)
+                .await;
+        } else {
+            self.order_bids(round, &mut bids);
+            self.next_solver(round, target, bids, self.board.robots())
+                .await;
+        }
+    }
+
+    /// lowest bid first, with ties broken by the room's `TieBreak`.
+    fn order_bids(&self, round: u32, bids: &mut [Bid]) {
+        // the sorts are stable, so anything still tied stays in the order it was bid
+        bids.sort_by_key(|bid| bid.at);
+        match self.settings.tie_break {
+            TieBreak::Earliest => {}
+            TieBreak::Random => {
+                bids.shuffle(&mut StdRng::seed_from_u64(
+                    self.seed.wrapping_add(round.into()),
+                ));
+            }
+            TieBreak::Score => bids.sort_by_key(|bid| {
+                Reverse(
+                    self.players
+                        .get(&bid.username)
+                        .map_or(0, |player| player.points),
+                )
+            }),
+        }
+        bids.sort_by_key(|bid| bid.moves);
+    }
+
+    /// gives the next bidder in line a go, with the robots back where they started the round.
+    async fn next_solver(
+        &mut self,
+        round: u32,
+        target: Target,
+        mut queue: Vec<Bid>,
+        origin: [Position; 4],
+    ) {
+        self.flush_board_state(true).await;
+        if queue.is_empty() {
+            return self.end_round(round, target, origin, None).await;
+        }
+
+        let bid = queue.remove(0);
+        self.board.place_robots(origin);
+        self.set_phase(Phase::Solving {
+            round,
+            target,
+            solver: bid.username,
+            bid: bid.moves,
+            moves: 0,
+            history: Vec::new(),
+            queue,
+            origin,
+            deadline: Instant::now() + Duration::from_secs(self.settings.solve_seconds),
+        })
+        .await;
+    }
+
+    async fn move_robot(
+        &mut self,
+        username: Arc<str>,
+        robot: Option<Robot>,
+        direction: Direction,
+    ) -> Result<(), RoomError> {
+        if !matches!(&self.phase, Some(Phase::Solving { solver, .. }) if *solver == username) {
+            return self.reject_move(username, MoveRejection::NotYourTurn).await;
+        }
+        let Some(robot) = robot else {
+            return self.reject_move(username, MoveRejection::NoSuchRobot).await;
+        };
+        if self
+            .players
+            .get(&username)
+            .is_some_and(|player| !player.robots.is_empty() && !player.robots.contains(&robot))
+        {
+            return self
+                .reject_move(username, MoveRejection::NotYourRobot)
+                .await;
+        }
+        let from = self.board.robot(robot);
+        let to = self.board.slide(robot, direction);
+        // moves that go nowhere aren't counted against the bid
+        if from == to {
+            return self.reject_move(username, MoveRejection::CannotMove).await;
+        }
+
+        let Some(Phase::Solving {
+            round,
+            target,
+            bid,
+            moves,
+            history,
+            origin,
+            ..
+        }) = &mut self.phase
+        else {
+            unreachable!("checked above");
+        };
+        *moves += 1;
+        history.push(RobotMove { robot, from, to });
+        let (round, target, moves, bid, origin) = (*round, *target, *moves, *bid, *origin);
+
+        self.board.place_robot(robot, to);
+        self.send_all(Arc::new(ServerMessage::RobotMoved { robot, from, to }))
+            .await;
+        self.board_changed = Some(Instant::now());
+
+        if target.kind.accepts(robot) && to == target.position {
+            self.record_attempt(true);
+            self.award(username.clone(), 1).await?;
+            // the award may have been the winning one
+            if !matches!(self.phase, Some(Phase::GameOver)) {
+                self.end_round(round, target, origin, Some(username)).await;
+            }
+        } else if moves >= bid {
+            self.fail_attempt().await;
+        }
+        Ok(())
+    }
+
+    /// tells the player why their move didn't happen. nobody else hears about it.
+    async fn reject_move(
+        &mut self,
+        username: Arc<str>,
+        reason: MoveRejection,
+    ) -> Result<(), RoomError> {
+        self.deliver(username, Arc::new(ServerMessage::MoveRejected { reason }))
+            .await
+    }
+
+    /// takes back the solver's last move, putting the robot back where it came from.
+    async fn undo(&mut self, username: Arc<str>) -> Result<(), RoomError> {
+        let Some(Phase::Solving {
+            solver,
+            moves,
+            history,
+            ..
+        }) = &mut self.phase
+        else {
+            return Err(RoomError::WrongPhase);
+        };
+        if *solver != username {
+            return Err(RoomError::NotSolver);
+        }
+
+        let undone = history.pop().ok_or(RoomError::NothingToUndo)?;
+        *moves -= 1;
+
+        self.board.place_robot(undone.robot, undone.from);
+        self.send_all(Arc::new(ServerMessage::MoveUndone {
+            robot: undone.robot,
+            from: undone.to,
+            to: undone.from,
+        }))
+        .await;
+        self.board_changed = Some(Instant::now());
+        Ok(())
+    }
+
+    async fn fail_attempt(&mut self) {
+        let Some(Phase::Solving {
+            round,
+            target,
+            solver,
+            queue,
+            origin,
+            ..
+        }) = self.phase.clone()
+        else {
+            return;
+        };
+        self.record_attempt(false);
+
+        // the solver may have left or been kicked, in which case there's nobody to penalise
+        if let Some(player) = self.players.get(&solver) {
+            // never below the floor, but nobody already under it gets pulled up to it either
+            let penalty = self
+                .settings
+                .fail_penalty
+                .min(player.points.saturating_sub(self.settings.points_floor))
+                .max(0);
+            if penalty > 0 {
+                // can't fail, since they're still in the room
+                let _ = self.award(solver, -penalty).await;
+            }
+        }
+        self.next_solver(round, target, queue, origin).await;
+    }
+
+    /// adds the current solving attempt to its round's history, with the moves that stood.
+    fn record_attempt(&mut self, solved: bool) {
+        let Some(Phase::Solving {
+            round,
+            solver,
+            history,
+            ..
+        }) = &self.phase
+        else {
+            return;
+        };
+        // rooms restored mid-round have nothing to add to
+        if let Some(entry) = self
+            .history
+            .back_mut()
+            .filter(|entry| entry.round == *round)
+        {
+            entry.attempts.push(Attempt {
+                solver: solver.clone(),
+                moves: history.clone(),
+                solved,
+            });
+        }
+    }
+
+    /// sends the player every attempt at `round` so far, as long as it's one of the latest.
+    async fn send_history(&mut self, username: Arc<str>, round: u32) -> Result<(), RoomError> {
+        let entry = self
+            .history
+            .iter()
+            .find(|entry| entry.round == round)
+            .ok_or(RoomError::RoundNotFound(round))?;
+        let message = ServerMessage::MoveHistory {
+            round,
+            target: entry.target,
+            attempts: entry.attempts.clone(),
+        };
+        self.send_one(username, Arc::new(message)).await
+    }
+
+    /// wraps up the round, revealing how few moves the target could have taken from `origin`.
+    async fn end_round(
+        &mut self,
+        round: u32,
+        target: Target,
+        origin: [Position; 4],
+        winner: Option<Arc<str>>,
+    ) {
+        self.flush_board_state(true).await;
+        self.send_all(Arc::new(ServerMessage::RoundOver { round, winner }))
+            .await;
+
+        // usually long done by now. rooms restored mid-round never started a search
+        let optimum = self.optimum.take().unwrap_or_else(|| {
+            let mut board = self.board.clone();
+            board.place_robots(origin);
+            solve_in_background(board, target)
+        });
+        let optimal_moves = optimum.await.ok().flatten();
+        self.send_all(Arc::new(ServerMessage::Solution {
+            round,
+            optimal_moves,
+        }))
+        .await;
+        self.begin_round(round + 1).await;
+    }
+
+    /// counts down the current phase, moving on once its time is up. called about once a second.
+    pub async fn tick(&mut self) {
+        self.flush_typing().await;
+        self.flush_board_state(false).await;
+
+        let Some(deadline) = self.phase.as_ref().and_then(Phase::deadline) else {
+            return;
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if !remaining.is_zero() {
+            let seconds = remaining.as_millis().div_ceil(1000) as u64;
+            let delivery = self
+                .broadcast(Arc::new(ServerMessage::TimeRemaining { seconds }))
+                .await;
+            // with everyone gone there's nobody to wait for, so the game plays itself out a step
+            // a tick rather than running out every clock
+            if delivery.delivered > 0 {
+                return;
+            }
+            tracing::debug!("nobody is connected, moving on");
+        }
+        if let Some(Phase::Bidding { .. }) = self.phase {
+            self.end_bidding().await;
+        } else {
+            self.fail_attempt().await;
+        }
+    }
+
+    pub async fn announce_shutdown(&mut self, grace_seconds: u64) {
+        self.send_all(Arc::new(ServerMessage::ServerShutdown { grace_seconds }))
+            .await;
+    }
+
+    pub async fn award(&mut self, username: Arc<str>, delta: i32) -> Result<(), RoomError> {
+        let player = self
+            .players
+            .get_mut(&username)
+            .ok_or(RoomError::PlayerNotFound(username.clone()))?;
+        player.points = player.points.saturating_add(delta);
+
+        // even without points, the order would give the scores away
+        if !self.scores_hidden() {
+            let standings = self.standings();
+            self.send_all(Arc::new(ServerMessage::Leaderboard { standings }))
+                .await;
+        }
+        self.update_player(&username).await;
+
+        if let Some(round) = self.phase.as_ref().and_then(Phase::round)
+            && self.target_score_reached()
+        {
+            self.end_game(round).await;
+        }
+        Ok(())
+    }
+
+    /// tells everyone the player's current state in one message, so clients can replace their
+    /// row in the roster instead of piecing it together from the finer grained messages.
+    async fn update_player(&mut self, username: &Arc<str>) {
+        let Some(player) = self.players.get(username) else {
+            return;
+        };
+        let update = ServerMessage::PlayerUpdate {
+            descriptor: self.descriptor(username, player),
+            connected: player.channel_handle.is_some(),
+        };
+        self.send_all(Arc::new(update)).await;
+    }
+
+    /// a full snapshot of the room's state, as seen by `username`.
+    fn welcome(&self, username: Arc<str>) -> ServerMessage {
+        ServerMessage::Welcome {
+            code: self.code.clone(),
+            username,
+            players: self.descriptors(),
+            host: self.host.clone(),
+            phase: self.phase.clone(),
+            rounds: self.settings.rounds,
+            target_score: self.settings.target_score,
+            bid_seconds: self.settings.bid_seconds,
+            solve_seconds: self.settings.solve_seconds,
+            seconds_left: self
+                .phase
+                .as_ref()
+                .and_then(Phase::deadline)
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()).as_secs()),
+            board: self.board.clone(),
+        }
+    }
+
+    /// the remembered chats after `since`, or the last `CHAT_REPLAY` of them.
+    fn chats_since(&self, since: Option<u64>) -> Vec<ChatEntry> {
+        match since {
+            Some(since) => self
+                .chats
+                .iter()
+                .filter(|chat| chat.id > since)
+                .cloned()
+                .collect(),
+            None => self
+                .chats
+                .iter()
+                .skip(self.chats.len().saturating_sub(CHAT_REPLAY))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// everyone's roster row, as clients may see it.
+    fn descriptors(&self) -> Vec<PlayerDescriptor> {
+        self.players
+            .iter()
+            .map(|(username, player)| self.descriptor(username, player))
+            .collect()
+    }
+
+    /// the player's roster row, without their points while those are hidden.
+    fn descriptor(&self, username: &Arc<str>, player: &Player) -> PlayerDescriptor {
+        let mut descriptor = PlayerDescriptor::new(username, player);
+        if self.scores_hidden() {
+            descriptor.points = None;
+        }
+        descriptor
+    }
+
+    /// whether points are being kept from clients, as they are until the game ends if the host
+    /// asked for it.
+    fn scores_hidden(&self) -> bool {
+        self.settings.hide_scores_until_end && !matches!(self.phase, Some(Phase::GameOver))
+    }
+
+    /// when the game ended, if it has.
+    pub fn finished(&self) -> Option<Instant> {
+        self.finished
+    }
+
+    /// when the room was created, as long as its host has yet to connect.
+    pub fn awaiting_host(&self) -> Option<Instant> {
+        self.awaiting_host
+    }
+
+    /// the final standings, once the game is over.
+    pub fn results(&self) -> Result<Results, RoomError> {
+        if !matches!(self.phase, Some(Phase::GameOver)) {
+            return Err(RoomError::GameNotOver);
+        }
+        let standings = self.standings();
+        let winner = match standings.as_slice() {
+            [first, second, ..] if first.points == second.points => None,
+            [first, ..] => Some(first.username.clone()),
+            [] => None,
+        };
+        Ok(Results {
+            standings,
+            winner,
+            seed: self.seed,
+        })
+    }
+
+    /// player descriptors ordered from most to fewest points, with ties broken by name. points are
+    /// always included, so this is only for sending once they're no longer hidden.
+    fn standings(&self) -> Vec<PlayerDescriptor> {
+        let mut standings = self
+            .players
+            .iter()
+            .map(|(username, player)| PlayerDescriptor::new(username, player))
+            .collect::<Vec<_>>();
+        standings.sort_by(|a, b| {
+            b.points
+                .cmp(&a.points)
+                .then_with(|| a.username.cmp(&b.username))
+        });
+        standings
+    }
+
+    /// opens a channel to the player, identified by the returned connection id. if they're
+    /// already connected, `evict` drops their old channel in favour of the new one, otherwise
+    /// connecting fails. they're then caught up on the chats sent after `since`, or the last few
+    /// without it.
+    pub async fn connect(
+        &mut self,
+        username: Arc<str>,
+        evict: bool,
+        since: Option<u64>,
+    ) -> Result<Connection, RoomError> {
+        tracing::info!("player {username} connecting");
+        let player = self
+            .players
+            .get_mut(&username)
+            .ok_or(RoomError::PlayerNotFound(username.clone()))?;
+
+        if player.channel_handle.is_some() && !evict {
+            tracing::warn!("player {username} tried to connect while connected");
+            return Err(RoomError::PlayerConnected(username));
+        }
+
+        let reconnected = player.channel_handle.is_some();
+        if reconnected {
+            tracing::info!("player {username} evicted their old connection");
+            player.close(CloseReason::Replaced);
+        }
+
+        self.connections += 1;
+        let (sender, messages) = mpsc::channel::<Arc<ServerMessage>>(self.options.channel_capacity);
+        let (closer, closed) = oneshot::channel();
+        player.channel_handle = Some(sender);
+        player.closer = Some(closer);
+        player.connection = self.connections;
+        player.last_seen = Some(Instant::now());
+        if username == self.host {
+            self.awaiting_host = None;
+        }
+
+        self.send_one(username.clone(), Arc::new(self.welcome(username.clone())))
+            .await?;
+        let chats = self.chats_since(since);
+        if !chats.is_empty() {
+            self.send_one(
+                username.clone(),
+                Arc::new(ServerMessage::ChatHistory { chats }),
+            )
+            .await?;
+        }
+        // everyone else already saw them connect
+        if !reconnected {
+            self.send_all(Arc::new(ServerMessage::Connect {
+                username: username.clone(),
+            }))
+            .await;
+            self.update_player(&username).await;
+        }
+        Ok(Connection {
+            id: self.connections,
+            messages,
+            closed,
+        })
+    }
+
+    /// closes the player's channel, unless it has since been replaced by a newer connection.
+    pub async fn disconnect(
+        &mut self,
+        username: Arc<str>,
+        connection: u64,
+    ) -> Result<(), RoomError> {
+        let player = self
+            .players
+            .get_mut(&username)
+            .ok_or(RoomError::PlayerNotFound(username.clone()))?;
+        if player.connection != connection {
+            return Ok(());
+        }
+
+        tracing::info!("player {username} disconnecting");
+        player
+            .channel_handle
+            .take()
+            .ok_or(RoomError::PlayerDisconnected(username.clone()))?;
+        player.closer = None;
+        player.last_seen = Some(Instant::now());
+
+        // nobody's typing once they've gone, whatever they last said
+        player.typing.wanted = false;
+        player.typing.changed = None;
+        self.show_typing(username.clone()).await;
+
+        self.send_all(Arc::new(ServerMessage::Disconnect {
+            username: username.clone(),
+        }))
+        .await;
+        self.update_player(&username).await;
+        Ok(())
+    }
+
+    /// subscribes a spectator to everything sent to the whole room. a named one stays listed in
+    /// `spectators` until the returned `Watching` is dropped.
+    pub fn spectate(
+        &mut self,
+        name: Option<Username>,
+    ) -> (broadcast::Receiver<Arc<ServerMessage>>, Option<Watching>) {
+        tracing::info!("spectator connecting");
+        let watching = name.map(|name| {
+            self.connections += 1;
+            self.watchers
+                .lock()
+                .expect("watchers lock poisoned")
+                .insert(self.connections, name.into());
+            Watching {
+                watchers: self.watchers.clone(),
+                id: self.connections,
+            }
+        });
+        (self.spectators.subscribe(), watching)
+    }
+
+    /// how many are watching, and the names of those who gave one.
+    pub fn spectators(&self) -> Spectators {
+        let mut names = self
+            .watchers
+            .lock()
+            .expect("watchers lock poisoned")
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        Spectators {
+            count: self.spectators.receiver_count(),
+            names,
+        }
+    }
+
+    pub fn summary(&self) -> RoomSummary {
+        RoomSummary {
+            code: self.code.clone(),
+            host: self.host.clone(),
+            player_count: self.players.len(),
+            connected_count: self
+                .players
+                .values()
+                .filter(|player| player.channel_handle.is_some())
+                .count(),
+            spectator_count: self.spectators.receiver_count(),
+            started: self.phase.is_some(),
+        }
+    }
+
+    /// the room as it stands, for debugging. tokens and the password are left out.
+    pub fn state(&self) -> RoomState {
+        RoomState {
+            code: self.code.clone(),
+            host: self.host.clone(),
+            requires_password: self.password.is_some(),
+            players: self
+                .players
+                .iter()
+                .map(|(username, player)| PlayerState {
+                    descriptor: PlayerDescriptor::new(username, player),
+                    connection: player.channel_handle.as_ref().map(|_| player.connection),
+                    typing: player.typing.shown,
+                })
+                .collect(),
+            phase: self.phase.clone(),
+            settings: self.settings.clone(),
+            seed: self.seed,
+            board: self.board.clone(),
+            pile: self.pile.clone(),
+            spectators: self.spectators(),
+            connections: self.connections,
+            finished_seconds_ago: self.finished.map(|at| at.elapsed().as_secs()),
+            chats: self.chats.len(),
+            next_chat_id: self.next_chat_id,
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.settings.private
+    }
+
+    pub fn requires_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    pub fn check_password(&self, password: Option<Password>) -> Result<(), RoomError> {
+        match &self.password {
+            Some(expected) if password.as_ref() != Some(expected) => {
+                Err(RoomError::IncorrectPassword)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn authenticate(&self, token: &Token) -> Option<Arc<str>> {
+        self.tokens.get(&hash_token(token.expose())).cloned()
+    }
+
+    pub async fn join(
+        &mut self,
+        username: Username,
+        password: Option<Password>,
+    ) -> Result<Token, RoomError> {
+        let username: Arc<str> = username.into();
+        if self.phase.is_some() {
+            Err(RoomError::GameStarted)
+        } else if self.is_taken(&username) {
+            Err(RoomError::PlayerExists(username))
+        } else if self.players.len() >= self.settings.max_players {
+            Err(RoomError::RoomFull)
+        } else {
+            self.check_password(password)?;
+            self.players.insert(username.clone(), Player::default());
+            Counters::count(&self.options.counters.players_joined);
+
+            self.send_all(Arc::new(ServerMessage::Join {
+                username: username.clone(),
+            }))
+            .await;
+            self.update_player(&username).await;
+            Ok(self.create_token(username))
+        }
+    }
+
+    /// issues a fresh token to a player who lost theirs before the game started, revoking any
+    /// they had, along with their name as the room has it. players who are connected can't be
+    /// taken over this way.
+    pub fn rejoin(
+        &mut self,
+        username: Username,
+        password: Option<Password>,
+    ) -> Result<(Arc<str>, Token), RoomError> {
+        self.check_password(password)?;
+        if self.phase.is_some() {
+            return Err(RoomError::GameStarted);
+        }
+        // matched the way names are when joining, so whatever case it's asked for in finds them
+        let username = self
+            .find_player(username.as_str())
+            .ok_or_else(|| RoomError::PlayerNotFound(username.into()))?;
+        if self.players[&username].channel_handle.is_some() {
+            return Err(RoomError::PlayerConnected(username));
+        }
+
+        tracing::info!("player {username} rejoining");
+        self.tokens.retain(|_, owner| *owner != username);
+        Ok((username.clone(), self.create_token(username)))
+    }
+
+    /// adds a computer player on the host's behalf, returning its name and the channel the room
+    /// will send it messages on. whoever calls this is responsible for playing as the bot.
+    pub async fn add_bot(
+        &mut self,
+        username: Arc<str>,
+    ) -> Result<(Arc<str>, Receiver<Arc<ServerMessage>>), RoomError> {
+        if username != self.host {
+            return Err(RoomError::NotHost);
+        } else if self.phase.is_some() {
+            return Err(RoomError::GameStarted);
+        } else if self.players.len() >= self.settings.max_players {
+            return Err(RoomError::RoomFull);
+        }
+
+        let mut bot = Username::bot();
+        while self.is_taken(bot.as_str()) {
+            bot = Username::bot();
+        }
+        let bot: Arc<str> = bot.into();
+        tracing::info!("adding bot {bot}");
+
+        self.connections += 1;
+        let (sender, receiver) = mpsc::channel::<Arc<ServerMessage>>(self.options.channel_capacity);
+        self.players.insert(
+            bot.clone(),
+            Player {
+                ready: true,
+                channel_handle: Some(sender),
+                connection: self.connections,
+                ..Player::default()
+            },
+        );
+
+        let _ = self
+            .send_one(bot.clone(), Arc::new(self.welcome(bot.clone())))
+            .await;
+        self.send_all(Arc::new(ServerMessage::Join {
+            username: bot.clone(),
+        }))
+        .await;
+        self.send_all(Arc::new(ServerMessage::Connect {
+            username: bot.clone(),
+        }))
+        .await;
+        self.update_player(&bot).await;
+        self.auto_start().await;
+        Ok((bot, receiver))
+    }
+
+    /// removes a player from the room for good, along with any tokens they were issued.
+    pub async fn leave(&mut self, username: Arc<str>) -> Result<(), RoomError> {
+        if username == self.host {
+            return Err(RoomError::HostCannotLeave);
+        }
+        self.remove_player(username, CloseReason::Left).await
+    }
+
+    /// lets the host throw another player out of the room.
+    async fn kick(&mut self, username: Arc<str>, target: Arc<str>) -> Result<(), RoomError> {
+        if username != self.host {
+            return Err(RoomError::NotHost);
+        } else if target == self.host {
+            return Err(RoomError::HostCannotLeave);
+        }
+        self.remove_player(target, CloseReason::Kicked).await
+    }
+
+    /// hands the host's powers to another connected player.
+    async fn transfer_host(&mut self, username: Arc<str>, to: Arc<str>) -> Result<(), RoomError> {
+        if username != self.host {
+            return Err(RoomError::NotHost);
+        }
+        let player = self
+            .players
+            .get(&to)
+            .ok_or(RoomError::PlayerNotFound(to.clone()))?;
+        if player.channel_handle.is_none() {
+            return Err(RoomError::PlayerDisconnected(to));
+        }
+
+        self.host = to.clone();
+        self.send_all(Arc::new(ServerMessage::HostChanged { username: to }))
+            .await;
+        Ok(())
+    }
+
+    async fn remove_player(
+        &mut self,
+        username: Arc<str>,
+        reason: CloseReason,
+    ) -> Result<(), RoomError> {
+        let mut player = self
+            .players
+            .remove(&username)
+            .ok_or(RoomError::PlayerNotFound(username.clone()))?;
+        player.close(reason);
+
+        self.tokens.retain(|_, owner| *owner != username);
+
+        self.send_all(Arc::new(ServerMessage::Leave {
+            username: username.clone(),
+        }))
+        .await;
+
+        // their part in the round goes with them
+        match &mut self.phase {
+            Some(Phase::Bidding { bids, passed, .. }) => {
+                bids.retain(|bid| bid.username != username);
+                passed.retain(|player| *player != username);
+            }
+            Some(Phase::Solving { queue, .. }) => queue.retain(|bid| bid.username != username),
+            _ => {}
+        }
+        match &self.phase {
+            Some(Phase::Bidding { .. }) if self.everyone_bid() => self.end_bidding().await,
+            Some(Phase::Solving { solver, .. }) if *solver == username => self.fail_attempt().await,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// closes every player's connection, e.g. once the server is going down.
+    pub fn close_all(&mut self, reason: CloseReason) {
+        for player in self.players.values_mut() {
+            player.close(reason);
+        }
+    }
+
+    async fn send_one(
+        &mut self,
+        recipient: Arc<str>,
+        message: Arc<ServerMessage>,
+    ) -> Result<(), RoomError> {
+        tracing::info!("sending message {message:?} to {recipient}");
+        self.record(Some(recipient.clone()), &message);
+        self.deliver(recipient, message).await
+    }
+
+    fn record(&mut self, to: Option<Arc<str>>, message: &Arc<ServerMessage>) {
+        if self.log.len() == LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(LogEntry {
+            at: now_millis(),
+            to,
+            message: message.clone(),
+            delivery: None,
+        });
+    }
+
+    /// fills in how far a logged message got, once it's been sent.
+    fn record_delivery(&mut self, message: &Arc<ServerMessage>, delivery: Delivery) {
+        // anything the sending set off was logged after it, so it's near the back
+        if let Some(entry) = self
+            .log
+            .iter_mut()
+            .rev()
+            .find(|entry| Arc::ptr_eq(&entry.message, message))
+        {
+            entry.delivery = Some(delivery);
+        }
+    }
+
+    /// the messages the room sent most recently, oldest first.
+    pub fn log(&self) -> Vec<LogEntry> {
+        self.log.iter().cloned().collect()
+    }
+
+    /// like `send_one`, but without logging, for messages too frequent to be worth recording.
+    async fn deliver(
+        &mut self,
+        recipient: Arc<str>,
+        message: Arc<ServerMessage>,
+    ) -> Result<(), RoomError> {
+        let player = self
+            .players
+            .get_mut(&recipient)
+            .ok_or(RoomError::PlayerNotFound(recipient.clone()))?;
+        let sent = player
+            .channel_handle
+            .as_mut()
+            .ok_or(RoomError::PlayerDisconnected(recipient.clone()))?
+            .send(message)
+            .await;
+
+        // the connection went away without disconnecting yet, so do it for them rather than
+        // leave the player looking connected
+        if sent.is_err() {
+            tracing::warn!("player {recipient} stopped receiving messages");
+            let connection = player.connection;
+            Box::pin(self.disconnect(recipient.clone(), connection)).await?;
+            return Err(RoomError::PlayerDisconnected(recipient));
+        }
+        Ok(())
+    }
+
+    async fn send_all(&mut self, message: Arc<ServerMessage>) -> Delivery {
+        tracing::info!("sending message {message:?} to all");
+        self.record(None, &message);
+        self.broadcast(message).await
+    }
+
+    /// like `send_all`, but without logging.
+    async fn broadcast(&mut self, message: Arc<ServerMessage>) -> Delivery {
+        // only fails when no spectators are subscribed
+        let _ = self.spectators.send(message.clone());
+        self.fan_out(None, message).await
+    }
+
+    /// like `broadcast`, but leaving out one player.
+    async fn broadcast_except(
+        &mut self,
+        excluded: &Arc<str>,
+        message: Arc<ServerMessage>,
+    ) -> Delivery {
+        let _ = self.spectators.send(message.clone());
+        self.fan_out(Some(excluded), message).await
+    }
+
+    /// sends to every connected player at once, then disconnects anyone whose connection turned
+    /// out to be gone.
+    async fn fan_out(
+        &mut self,
+        excluded: Option<&Arc<str>>,
+        message: Arc<ServerMessage>,
+    ) -> Delivery {
+        let mut delivery = Delivery::default();
+        let results = join_all(
+            self.players
+                .iter_mut()
+                .filter(|(username, _)| Some(*username) != excluded)
+                .filter_map(|(username, player)| {
+                    let Some(sender) = player.channel_handle.as_mut() else {
+                        delivery.skipped_disconnected += 1;
+                        return None;
+                    };
+                    let connection = player.connection;
+                    let message = message.clone();
+                    Some(async move {
+                        let sent = sender.send(message).await.is_ok();
+                        (username.clone(), connection, sent)
+                    })
+                }),
+        )
+        .await;
+
+        let attempted = results.len();
+        let closed = results
+            .into_iter()
+            .filter(|(_, _, sent)| !sent)
+            .map(|(username, connection, _)| (username, connection))
+            .collect::<Vec<_>>();
+        delivery.failed = closed.len();
+        delivery.delivered = attempted - closed.len();
+        self.record_delivery(&message, delivery);
+        if closed.is_empty() {
+            return delivery;
+        }
+
+        let usernames = closed
+            .iter()
+            .map(|(username, _)| &**username)
+            .collect::<Vec<_>>();
+        tracing::warn!(
+            "{} players stopped receiving messages: {}",
+            closed.len(),
+            usernames.join(", ")
+        );
+        for (username, connection) in closed {
+            // their disconnect is announced with another broadcast, which may find more
+            let _ = Box::pin(self.disconnect(username, connection)).await;
+        }
+        delivery
+    }
+}
+
+#[derive(Debug, Default)]
+struct Player {
+    points: i32,
+    ready: bool,
+    color: Option<Color>,
+    // the robots they're responsible for, if the host handed any out
+    robots: Vec<Robot>,
+    channel_handle: Option<Sender<Arc<ServerMessage>>>,
+    // tells the connection why the room closed it, if it did
+    closer: Option<oneshot::Sender<CloseReason>>,
+    // which of the room's connections `channel_handle` belongs to
+    connection: u64,
+    // when they last connected or disconnected, `None` if they never have
+    last_seen: Option<Instant>,
+    chat_limiter: ChatLimiter,
+    typing: Typing,
+}
+
+/// whether a player is typing, as they last said and as everyone else was last told.
+#[derive(Debug, Default)]
+struct Typing {
+    wanted: bool,
+    shown: bool,
+    changed: Option<Instant>,
+}
+
+impl Typing {
+    fn settled(&self) -> bool {
+        self.changed
+            .is_none_or(|changed| changed.elapsed() >= TYPING_DEBOUNCE)
+    }
+}
+
+impl Player {
+    /// drops the player's channel, telling whoever serves it why.
+    fn close(&mut self, reason: CloseReason) {
+        // the reason goes first, so it's there by the time the channel is seen to close
+        if let Some(closer) = self.closer.take() {
+            let _ = closer.send(reason);
+        }
+        self.channel_handle = None;
+    }
+}
+
+/// a player's live connection to the room, for whoever is serving it.
+#[derive(Debug)]
+pub struct Connection {
+    pub id: u64,
+    pub messages: Receiver<Arc<ServerMessage>>,
+    // resolves if the room closes the connection itself, rather than the player hanging up
+    pub closed: oneshot::Receiver<CloseReason>,
+}
+
+/// why the room closed a player's connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    Replaced,
+    Left,
+    Kicked,
+    ServerShutdown,
+}
+
+/// a token bucket holding up to `CHAT_BURST` messages, refilled continuously.
+#[derive(Debug)]
+struct ChatLimiter {
+    allowance: f64,
+    checked: Instant,
+}
+
+impl Default for ChatLimiter {
+    fn default() -> Self {
+        Self {
+            allowance: CHAT_BURST,
+            checked: Instant::now(),
+        }
+    }
+}
+
+impl ChatLimiter {
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.checked).as_secs_f64();
+        self.allowance = (self.allowance + elapsed * CHAT_PER_SECOND).min(CHAT_BURST);
+        self.checked = now;
+
+        if self.allowance < 1.0 {
+            return false;
+        }
+        self.allowance -= 1.0;
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerDescriptor {
+    username: Arc<str>,
+    // left out while the room is hiding scores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    points: Option<i32>,
+    ready: bool,
+    connected: bool,
+    // how long ago a disconnected player dropped, `None` while connected or if they never were
+    away_seconds: Option<u64>,
+    // `None` until they pick one
+    color: Option<Color>,
+    // the only robots they can move, unless empty
+    #[serde(default)]
+    robots: Vec<Robot>,
+}
+
+impl PlayerDescriptor {
+    fn new(username: &Arc<str>, player: &Player) -> Self {
+        Self {
+            username: username.clone(),
+            points: Some(player.points),
+            ready: player.ready,
+            connected: player.channel_handle.is_some(),
+            away_seconds: match (&player.channel_handle, player.last_seen) {
+                (None, Some(last_seen)) => Some(last_seen.elapsed().as_secs()),
+                _ => None,
+            },
+            color: player.color,
+            robots: player.robots.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomSummary {
+    code: Arc<str>,
+    host: Arc<str>,
+    pub player_count: usize,
+    pub connected_count: usize,
+    pub spectator_count: usize,
+    started: bool,
+}
+
+type Watchers = Arc<std::sync::Mutex<BTreeMap<u64, Arc<str>>>>;
+
+/// keeps a named spectator listed while their connection is open.
+#[derive(Debug)]
+pub struct Watching {
+    watchers: Watchers,
+    id: u64,
+}
+
+impl Drop for Watching {
+    fn drop(&mut self) {
+        self.watchers
+            .lock()
+            .expect("watchers lock poisoned")
+            .remove(&self.id);
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Spectators {
+    count: usize,
+    // only those who gave a name, in alphabetical order
+    names: Vec<Arc<str>>,
+}
+
+/// a chat as it stands now, after any edits.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatEntry {
+    id: u64,
+    username: Arc<str>,
+    text: Arc<str>,
+}
+
+/// what `/admin/rooms/{code}/state` shows of a room.
+#[derive(Serialize, Debug)]
+pub struct RoomState {
+    code: Arc<str>,
+    host: Arc<str>,
+    requires_password: bool,
+    players: Vec<PlayerState>,
+    phase: Option<Phase>,
+    settings: RoomSettings,
+    seed: u64,
+    board: Board,
+    pile: Vec<Target>,
+    spectators: Spectators,
+    connections: u64,
+    finished_seconds_ago: Option<u64>,
+    // how many chats are remembered
+    chats: usize,
+    next_chat_id: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct PlayerState {
+    #[serde(flatten)]
+    descriptor: PlayerDescriptor,
+    // which of the room's connections is theirs, while they're connected
+    connection: Option<u64>,
+    typing: bool,
+}
+
+/// how a finished game came out.
+#[derive(Serialize, Debug, Clone)]
+pub struct Results {
+    standings: Vec<PlayerDescriptor>,
+    // `None` if the lead was shared
+    winner: Option<Arc<str>>,
+    seed: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct RobotMove {
+    robot: Robot,
+    from: Position,
+    to: Position,
+}
+
+/// one player's go at a round, with the moves that stood when it ended.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attempt {
+    solver: Arc<str>,
+    moves: Vec<RobotMove>,
+    solved: bool,
+}
+
+/// every attempt at a round, for looking back on how it was solved.
+#[derive(Debug, Clone)]
+struct RoundHistory {
+    round: u32,
+    target: Target,
+    attempts: Vec<Attempt>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bid {
+    username: Arc<str>,
+    moves: u32,
+    // milliseconds since the unix epoch, for breaking ties
+    #[serde(default)]
+    at: u64,
+}
+
+// deadlines aren't saved, so a phase restored from a snapshot ends on its first tick
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum Phase {
+    Bidding {
+        round: u32,
+        target: Target,
+        bids: Vec<Bid>,
+        // players sitting this target out
+        passed: Vec<Arc<str>>,
+        #[serde(skip, default = "Instant::now")]
+        deadline: Instant,
+    },
+    Solving {
+        round: u32,
+        target: Target,
+        solver: Arc<str>,
+        bid: u32,
+        moves: u32,
+        // the moves made so far this attempt, oldest first
+        history: Vec<RobotMove>,
+        // the bidders still waiting for a go, lowest bid first
+        queue: Vec<Bid>,
+        // where the robots were at the start of the round
+        origin: [Position; 4],
+        #[serde(skip, default = "Instant::now")]
+        deadline: Instant,
+    },
+    GameOver,
+}
+
+impl Phase {
+    /// the round being played, if the game is still going.
+    fn round(&self) -> Option<u32> {
+        match self {
+            Self::Bidding { round, .. } | Self::Solving { round, .. } => Some(*round),
+            Self::GameOver => None,
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        match self {
+            Self::Bidding { deadline, .. } | Self::Solving { deadline, .. } => Some(*deadline),
+            Self::GameOver => None,
+        }
+    }
+}
+
+/// a quick reaction, kept to a fixed set so clients only need artwork for these.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Emote {
+    Thumbsup,
+    Thumbsdown,
+    Laugh,
+    Think,
+    Wow,
+    Clap,
+}
+
+/// what a player shows up as, kept to a fixed palette so every client can draw it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Color {
+    Red,
+    Orange,
+    Yellow,
+    Lime,
+    Green,
+    Teal,
+    Cyan,
+    Blue,
+    Indigo,
+    Purple,
+    Pink,
+    Brown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlayerMessage {
+    Chat {
+        text: Arc<str>,
+    },
+    ChatEdit {
+        id: u64,
+        text: Arc<str>,
+    },
+    ChatDelete {
+        id: u64,
+    },
+    Emote {
+        kind: Emote,
+    },
+    Whisper {
+        to: Username,
+        text: Arc<str>,
+    },
+    Typing {
+        active: bool,
+    },
+    Start {
+        // skips waiting for everyone to be ready
+        #[serde(default)]
+        force: bool,
+    },
+    SetReady {
+        ready: bool,
+    },
+    SetColor {
+        color: Color,
+    },
+    AdjustPoints {
+        username: Arc<str>,
+        delta: i32,
+    },
+    Ping {
+        nonce: u64,
+    },
+    Resync,
+    // asks for a `move_history` of one of the latest rounds
+    RequestHistory {
+        round: u32,
+    },
+    // leaves the room for good, rather than just disconnecting
+    Leave,
+    Bid {
+        moves: u32,
+    },
+    Pass,
+    Undo,
+    Kick {
+        username: Arc<str>,
+    },
+    // `None` for nobody
+    AssignRobot {
+        username: Option<Arc<str>>,
+        robot: Robot,
+    },
+    TransferHost {
+        to: Username,
+    },
+    Move {
+        // `None` for a robot that isn't on the board, so it can be rejected as such
+        #[serde(deserialize_with = "known_robot")]
+        robot: Option<Robot>,
+        direction: Direction,
+    },
+}
+
+fn known_robot<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Robot>, D::Error> {
+    Ok(Robot::deserialize(deserializer).ok())
+}
+
+/// why a `Bid` was turned down.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BidRejection {
+    /// bids start at one move.
+    TooLow,
+    /// more moves than any target could need.
+    TooHigh,
+}
+
+/// why a `Move` was turned down.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveRejection {
+    /// the sender isn't the one solving, or nobody is.
+    NotYourTurn,
+    NoSuchRobot,
+    /// the sender has robots assigned to them, and this isn't one.
+    NotYourRobot,
+    /// the robot is already up against a wall or another robot in that direction.
+    CannotMove,
+}
+
+/// a message as a connection sent it, numbered so clients can notice one going missing and
+/// resync.
+#[derive(Serialize, Debug)]
+pub struct Envelope<'a> {
+    // counts up across everything one connection is sent, so any gap is a lost message
+    pub seq: u64,
+    #[serde(flatten)]
+    pub message: &'a ServerMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Hello {
+        protocol_version: u32,
+    },
+    Join {
+        username: Arc<str>,
+    },
+    Leave {
+        username: Arc<str>,
+    },
+    Connect {
         username: Arc<str>,
     },
     Disconnect {
         username: Arc<str>,
     },
     Welcome {
+        code: Arc<str>,
         username: Arc<str>,
         players: Vec<PlayerDescriptor>,
         host: Arc<str>,
         phase: Option<Phase>,
+        rounds: u32,
+        // `None` unless the game also ends when someone reaches a score
+        target_score: Option<i32>,
+        bid_seconds: u64,
+        solve_seconds: u64,
+        // until the current phase times out, `None` if it doesn't
+        seconds_left: Option<u64>,
+        board: Board,
     },
     Chat {
+        id: u64,
         username: Arc<str>,
         text: Arc<str>,
     },
+    // recent chats, oldest first, sent just after `Welcome` to catch a connecting player up
+    ChatHistory {
+        chats: Vec<ChatEntry>,
+    },
+    ChatEdited {
+        id: u64,
+        text: Arc<str>,
+    },
+    ChatDeleted {
+        id: u64,
+    },
+    Emote {
+        username: Arc<str>,
+        kind: Emote,
+    },
+    Whisper {
+        from: Arc<str>,
+        to: Arc<str>,
+        text: Arc<str>,
+    },
+    Typing {
+        username: Arc<str>,
+        active: bool,
+    },
+    PhaseChanged {
+        phase: Phase,
+        // how long the new phase lasts, `None` if it doesn't time out
+        seconds: Option<u64>,
+    },
+    Leaderboard {
+        standings: Vec<PlayerDescriptor>,
+    },
+    Error {
+        code: String,
+        message: String,
+    },
+    Pong {
+        nonce: u64,
+    },
+    ServerShutdown {
+        grace_seconds: u64,
+    },
+    BidPlaced {
+        username: Arc<str>,
+        moves: u32,
+    },
+    RobotMoved {
+        robot: Robot,
+        from: Position,
+        to: Position,
+    },
+    Passed {
+        username: Arc<str>,
+    },
+    MoveRejected {
+        reason: MoveRejection,
+    },
+    BidRejected {
+        reason: BidRejection,
+    },
+    MoveUndone {
+        robot: Robot,
+        from: Position,
+        to: Position,
+    },
+    // every robot's position in `Robot::ALL` order once a burst of moves settles, in place of
+    // `RobotMoved` and `MoveUndone` for clients that ask for it when connecting
+    BoardState {
+        robots: [Position; 4],
+    },
+    TimeRemaining {
+        seconds: u64,
+    },
+    RoundOver {
+        round: u32,
+        winner: Option<Arc<str>>,
+    },
+    // `None` when the solution is too long for the server to search for
+    Solution {
+        round: u32,
+        optimal_moves: Option<u32>,
+    },
+    // every attempt at a round so far, oldest first, in answer to `RequestHistory`
+    MoveHistory {
+        round: u32,
+        target: Target,
+        attempts: Vec<Attempt>,
+    },
+    // the seed the board and targets were dealt from, kept back until now so nobody can work
+    // out the targets ahead of time
+    GameOver {
+        standings: Vec<PlayerDescriptor>,
+        seed: u64,
+    },
+    // the host started over after a game ended, back in the lobby with everyone's points and
+    // readiness cleared
+    GameReset {
+        players: Vec<PlayerDescriptor>,
+        board: Board,
+    },
+    // the host changed the settings before the game started. the board is sent along in case
+    // its size changed
+    SettingsChanged {
+        settings: RoomSettings,
+        board: Board,
+    },
+    ReadyChanged {
+        username: Arc<str>,
+        ready: bool,
+    },
+    HostChanged {
+        username: Arc<str>,
+    },
+    ColorChanged {
+        username: Arc<str>,
+        color: Color,
+    },
+    // `None` once the robot is nobody's
+    RobotAssigned {
+        robot: Robot,
+        username: Option<Arc<str>>,
+    },
+    // only sent to clients that ask for it when connecting
+    PlayerUpdate {
+        descriptor: PlayerDescriptor,
+        connected: bool,
+    },
 }
@@ -0,0 +1,295 @@
+//! one example of every message, documenting the wire format for client code.
+
+use std::sync::Arc;
+
+use tokio::time::Instant;
+
+use super::{
+    Attempt, Bid, BidRejection, ChatEntry, Color, Emote, MoveRejection, Phase, PlayerDescriptor,
+    PlayerMessage, RobotMove, RoomSettings, ServerMessage, Username,
+};
+use crate::game::{Board, Direction, Position, Robot, Target, TargetKind};
+
+fn target() -> Target {
+    Target {
+        kind: TargetKind::Robot(Robot::Red),
+        position: Position::new(1, 2),
+    }
+}
+
+fn board() -> Board {
+    let mut board = Board::new(4);
+    board.add_wall(Position::new(1, 2), Direction::Up);
+    board.add_wall(Position::new(1, 2), Direction::Left);
+    board.add_target(target());
+    board.add_target(Target {
+        kind: TargetKind::Wildcard,
+        position: Position::new(3, 2),
+    });
+    board
+}
+
+fn players() -> Vec<PlayerDescriptor> {
+    vec![
+        PlayerDescriptor {
+            username: "robbie".into(),
+            points: Some(3),
+            ready: true,
+            connected: true,
+            away_seconds: None,
+            color: Some(Color::Teal),
+            robots: vec![Robot::Red],
+        },
+        PlayerDescriptor {
+            username: "roberta".into(),
+            points: Some(1),
+            ready: false,
+            connected: false,
+            away_seconds: Some(42),
+            color: None,
+            robots: Vec::new(),
+        },
+    ]
+}
+
+fn bid(username: &str, moves: u32) -> Bid {
+    Bid {
+        username: username.into(),
+        moves,
+        at: 1_700_000_000_000,
+    }
+}
+
+impl PlayerMessage {
+    pub fn examples() -> Vec<Self> {
+        vec![
+            Self::Chat {
+                text: "hiiii".into(),
+            },
+            Self::ChatEdit {
+                id: 3,
+                text: "hiiiii".into(),
+            },
+            Self::ChatDelete { id: 3 },
+            Self::Emote {
+                kind: Emote::Thumbsup,
+            },
+            Self::Whisper {
+                to: Username::validate("roberta".into()).unwrap(),
+                text: "psst".into(),
+            },
+            Self::Typing { active: true },
+            Self::Start { force: false },
+            Self::SetReady { ready: true },
+            Self::SetColor { color: Color::Teal },
+            Self::AdjustPoints {
+                username: "robbie".into(),
+                delta: -1,
+            },
+            Self::Ping { nonce: 7 },
+            Self::Resync,
+            Self::RequestHistory { round: 1 },
+            Self::Leave,
+            Self::Bid { moves: 5 },
+            Self::Pass,
+            Self::Undo,
+            Self::Kick {
+                username: "roberta".into(),
+            },
+            Self::AssignRobot {
+                username: Some("robbie".into()),
+                robot: Robot::Red,
+            },
+            Self::TransferHost {
+                to: Username::validate("roberta".into()).unwrap(),
+            },
+            Self::Move {
+                robot: Some(Robot::Blue),
+                direction: Direction::Left,
+            },
+        ]
+    }
+}
+
+impl ServerMessage {
+    pub fn examples() -> Vec<Self> {
+        let username: Arc<str> = "robbie".into();
+
+        vec![
+            Self::Hello {
+                protocol_version: super::PROTOCOL_VERSION,
+            },
+            Self::Join {
+                username: username.clone(),
+            },
+            Self::Leave {
+                username: username.clone(),
+            },
+            Self::Connect {
+                username: username.clone(),
+            },
+            Self::Disconnect {
+                username: username.clone(),
+            },
+            Self::Welcome {
+                code: "ABCD".into(),
+                username: username.clone(),
+                players: players(),
+                host: username.clone(),
+                phase: Some(Phase::Bidding {
+                    round: 1,
+                    target: target(),
+                    bids: vec![bid("robbie", 5)],
+                    passed: vec!["roberta".into()],
+                    deadline: Instant::now(),
+                }),
+                rounds: 17,
+                target_score: Some(10),
+                bid_seconds: 60,
+                solve_seconds: 60,
+                seconds_left: Some(42),
+                board: board(),
+            },
+            Self::Chat {
+                id: 3,
+                username: username.clone(),
+                text: "hiiii".into(),
+            },
+            Self::ChatHistory {
+                chats: vec![ChatEntry {
+                    id: 3,
+                    username: username.clone(),
+                    text: "hiiii".into(),
+                }],
+            },
+            Self::ChatEdited {
+                id: 3,
+                text: "hiiiii".into(),
+            },
+            Self::ChatDeleted { id: 3 },
+            Self::Emote {
+                username: username.clone(),
+                kind: Emote::Laugh,
+            },
+            Self::Whisper {
+                from: username.clone(),
+                to: "roberta".into(),
+                text: "psst".into(),
+            },
+            Self::Typing {
+                username: username.clone(),
+                active: true,
+            },
+            Self::PhaseChanged {
+                phase: Phase::Solving {
+                    round: 1,
+                    target: target(),
+                    solver: username.clone(),
+                    bid: 5,
+                    moves: 1,
+                    history: vec![RobotMove {
+                        robot: Robot::Blue,
+                        from: Position::new(3, 0),
+                        to: Position::new(1, 0),
+                    }],
+                    queue: vec![bid("roberta", 7)],
+                    origin: board().robots(),
+                    deadline: Instant::now(),
+                },
+                seconds: Some(60),
+            },
+            Self::PhaseChanged {
+                phase: Phase::GameOver,
+                seconds: None,
+            },
+            Self::GameReset {
+                players: players(),
+                board: board(),
+            },
+            Self::SettingsChanged {
+                settings: RoomSettings::default(),
+                board: board(),
+            },
+            Self::Leaderboard {
+                standings: players(),
+            },
+            Self::Error {
+                code: "not_host".into(),
+                message: "only the host can do that".into(),
+            },
+            Self::Pong { nonce: 7 },
+            Self::ServerShutdown { grace_seconds: 5 },
+            Self::BidPlaced {
+                username: username.clone(),
+                moves: 5,
+            },
+            Self::RobotMoved {
+                robot: Robot::Blue,
+                from: Position::new(3, 0),
+                to: Position::new(1, 0),
+            },
+            Self::Passed {
+                username: username.clone(),
+            },
+            Self::MoveRejected {
+                reason: MoveRejection::CannotMove,
+            },
+            Self::BidRejected {
+                reason: BidRejection::TooHigh,
+            },
+            Self::MoveUndone {
+                robot: Robot::Blue,
+                from: Position::new(1, 0),
+                to: Position::new(3, 0),
+            },
+            Self::BoardState {
+                robots: board().robots(),
+            },
+            Self::TimeRemaining { seconds: 30 },
+            Self::RoundOver {
+                round: 1,
+                winner: Some(username.clone()),
+            },
+            Self::Solution {
+                round: 1,
+                optimal_moves: Some(4),
+            },
+            Self::MoveHistory {
+                round: 1,
+                target: target(),
+                attempts: vec![Attempt {
+                    solver: username.clone(),
+                    moves: vec![RobotMove {
+                        robot: Robot::Blue,
+                        from: Position::new(3, 0),
+                        to: Position::new(1, 0),
+                    }],
+                    solved: false,
+                }],
+            },
+            Self::GameOver {
+                standings: players(),
+                seed: 42,
+            },
+            Self::ReadyChanged {
+                username: username.clone(),
+                ready: true,
+            },
+            Self::HostChanged {
+                username: username.clone(),
+            },
+            Self::ColorChanged {
+                username: username.clone(),
+                color: Color::Teal,
+            },
+            Self::RobotAssigned {
+                robot: Robot::Red,
+                username: Some(username),
+            },
+            Self::PlayerUpdate {
+                descriptor: players().remove(0),
+                connected: true,
+            },
+        ]
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
+use rand::{RngCore, rng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+/// a secret that proves who a request comes from. `Debug` never shows the bytes, so a token can
+/// sit in anything that might get logged; reading them takes an explicit `expose`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token(Box<[u8]>);
+
+impl Token {
+    pub fn generate(len: usize) -> Self {
+        let mut token = vec![0; len].into_boxed_slice();
+        rng().fill_bytes(&mut token);
+        Self(token)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self(bytes.into())
+    }
+
+    /// the raw bytes, for the few places that have to compare or hash them.
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// the form tokens are handed to clients in.
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.0)
+    }
+
+    // tokens used to be handed out in standard base64, which is still accepted
+    pub fn decode(token: &str) -> Option<Self> {
+        URL_SAFE_NO_PAD
+            .decode(token)
+            .or_else(|_| STANDARD.decode(token))
+            .ok()
+            .map(|token| Self(token.into()))
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Token(redacted)")
+    }
+}
+
+impl Serialize for Token {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+impl<'de> Deserialize<'de> for Token {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let token = String::deserialize(deserializer)?;
+        Self::decode(&token).ok_or_else(|| de::Error::custom("token is not base64"))
+    }
+}
@@ -0,0 +1,285 @@
+use std::{fs, io, path::Path, sync::Arc};
+
+use rand::{Rng, rng};
+use serde::{Deserialize, Deserializer, Serialize, de};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use unicode_normalization::{UnicodeNormalization, is_nfc};
+
+use super::CODE_CHARS;
+
+const MAX_USERNAME_LEN: usize = 24;
+const MAX_PASSWORD_LEN: usize = 128;
+const MAX_CHAT_LEN: usize = 500;
+const ANONYMOUS_PREFIX: &str = "Robot-";
+const BOT_PREFIX: &str = "Bot-";
+// keeps markup out of names, in case a client forgets to escape them
+const FORBIDDEN_USERNAME_CHARS: [char; 3] = ['<', '>', '&'];
+// digits and symbols commonly swapped in for letters to sneak words past a filter
+const LEETSPEAK: [(char, char); 8] = [
+    ('0', 'o'),
+    ('1', 'i'),
+    ('3', 'e'),
+    ('4', 'a'),
+    ('5', 's'),
+    ('7', 't'),
+    ('@', 'a'),
+    ('$', 's'),
+];
+const MASK: char = '*';
+
+#[derive(Error, Debug, Clone, Serialize)]
+pub enum ValidationError {
+    #[error("must not be empty")]
+    Empty,
+    #[error("must be at most {0} characters")]
+    TooLong(usize),
+    #[error("must not be only whitespace")]
+    OnlyWhitespace,
+    #[error("must be exactly {0} characters")]
+    WrongLength(usize),
+    #[error("must not contain '{0}'")]
+    InvalidCharacter(char),
+    #[error("must not contain blocked words")]
+    Blocked,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RoomCode(Arc<str>);
+
+impl RoomCode {
+    /// accepts codes in either case, normalizing them to the uppercase form they're generated in.
+    pub fn validate(code: &str, len: usize) -> Result<Self, ValidationError> {
+        let code = code.to_uppercase();
+
+        if code.chars().count() != len {
+            Err(ValidationError::WrongLength(len))
+        } else if let Some(c) = code.chars().find(|c| !CODE_CHARS.contains(c)) {
+            Err(ValidationError::InvalidCharacter(c))
+        } else {
+            Ok(Self(code.into()))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<RoomCode> for Arc<str> {
+    fn from(code: RoomCode) -> Self {
+        code.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Username(Arc<str>);
+
+// read through `validate`, so a name arriving in a message is held to the same rules as one
+// picked when joining
+impl<'de> Deserialize<'de> for Username {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let username = Arc::<str>::deserialize(deserializer)?;
+        Self::validate(username).map_err(de::Error::custom)
+    }
+}
+
+impl Username {
+    /// trims surrounding whitespace and normalizes to nfc, so the stored name is what other
+    /// players will see and names that only differ in how accents are encoded are the same.
+    pub fn validate(username: Arc<str>) -> Result<Self, ValidationError> {
+        let trimmed = username.trim();
+
+        if username.is_empty() {
+            Err(ValidationError::Empty)
+        } else if trimmed.is_empty() {
+            Err(ValidationError::OnlyWhitespace)
+        } else if trimmed.nfc().count() > MAX_USERNAME_LEN {
+            Err(ValidationError::TooLong(MAX_USERNAME_LEN))
+        } else if let Some(c) = trimmed
+            .chars()
+            .find(|c| c.is_control() || FORBIDDEN_USERNAME_CHARS.contains(c))
+        {
+            Err(ValidationError::InvalidCharacter(c))
+        } else if trimmed.len() == username.len() && is_nfc(trimmed) {
+            Ok(Self(username))
+        } else {
+            Ok(Self(trimmed.nfc().collect::<String>().into()))
+        }
+    }
+
+    /// like `validate`, but also turns away names with a word the filter blocks.
+    pub fn validate_with(username: Arc<str>, filter: &WordFilter) -> Result<Self, ValidationError> {
+        Self::validate(username)?.screen(filter)
+    }
+
+    /// turns away an already valid name if it has a word the filter blocks.
+    pub fn screen(self, filter: &WordFilter) -> Result<Self, ValidationError> {
+        if filter.matches(&self.0) {
+            Err(ValidationError::Blocked)
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// generates a random placeholder name like "Robot-7F3A" for players who didn't pick one.
+    pub fn anonymous() -> Self {
+        Self(format!("{ANONYMOUS_PREFIX}{:04X}", rng().random::<u16>()).into())
+    }
+
+    /// generates a name like "Bot-7F3A" for a computer player.
+    pub fn bot() -> Self {
+        Self(format!("{BOT_PREFIX}{:04X}", rng().random::<u16>()).into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// the form names are compared in when checking whether one is taken, so players can't share a
+/// name that only differs in case.
+pub fn fold_username(username: &str) -> String {
+    username.to_lowercase().nfc().collect()
+}
+
+impl From<Username> for Arc<str> {
+    fn from(username: Username) -> Self {
+        username.0
+    }
+}
+
+/// the text of a chat message or whisper.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatText(Arc<str>);
+
+impl ChatText {
+    /// trims surrounding whitespace, and allows line breaks but no other control characters.
+    pub fn validate(text: Arc<str>) -> Result<Self, ValidationError> {
+        let trimmed = text.trim();
+
+        if trimmed.is_empty() {
+            Err(ValidationError::Empty)
+        } else if trimmed.chars().count() > MAX_CHAT_LEN {
+            Err(ValidationError::TooLong(MAX_CHAT_LEN))
+        } else if let Some(c) = trimmed.chars().find(|&c| c.is_control() && c != '\n') {
+            Err(ValidationError::InvalidCharacter(c))
+        } else if trimmed.len() == text.len() {
+            Ok(Self(text))
+        } else {
+            Ok(Self(trimmed.into()))
+        }
+    }
+}
+
+impl ChatText {
+    /// stars out anything the filter blocks, leaving the rest of the message alone.
+    pub fn masked(self, filter: &WordFilter) -> Self {
+        if filter.matches(&self.0) {
+            Self(filter.mask(&self.0).into())
+        } else {
+            self
+        }
+    }
+}
+
+impl From<ChatText> for Arc<str> {
+    fn from(text: ChatText) -> Self {
+        text.0
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Password(Arc<str>);
+
+impl Password {
+    pub fn validate(password: Arc<str>) -> Result<Self, ValidationError> {
+        if password.is_empty() {
+            Err(ValidationError::Empty)
+        } else if password.chars().count() > MAX_PASSWORD_LEN {
+            Err(ValidationError::TooLong(MAX_PASSWORD_LEN))
+        } else if password.trim().is_empty() {
+            Err(ValidationError::OnlyWhitespace)
+        } else {
+            Ok(Self(password))
+        }
+    }
+}
+
+// compared in constant time so response timing doesn't leak how much of a guess was correct
+impl PartialEq for Password {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_bytes().ct_eq(other.0.as_bytes()).into()
+    }
+}
+
+impl Eq for Password {}
+
+/// words that aren't allowed in names or chat. matching ignores case and common leetspeak, and
+/// finds words inside longer ones too. the default filter blocks nothing.
+#[derive(Clone, Debug, Default)]
+pub struct WordFilter {
+    words: Vec<Vec<char>>,
+}
+
+impl WordFilter {
+    pub fn new<'a>(words: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            words: words
+                .into_iter()
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(|word| word.chars().map(normalize).collect())
+                .collect(),
+        }
+    }
+
+    /// reads one word per line, skipping blank lines and lines starting with '#'.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let list = fs::read_to_string(path)?;
+        Ok(Self::new(
+            list.lines()
+                .filter(|line| !line.trim_start().starts_with('#')),
+        ))
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        self.blocked(text).contains(&true)
+    }
+
+    pub fn mask(&self, text: &str) -> String {
+        text.chars()
+            .zip(self.blocked(text))
+            .map(|(c, blocked)| if blocked { MASK } else { c })
+            .collect()
+    }
+
+    /// which of the characters in `text` are part of a blocked word.
+    fn blocked(&self, text: &str) -> Vec<bool> {
+        // normalized one for one, so positions line up with the original characters
+        let normalized = text.chars().map(normalize).collect::<Vec<_>>();
+        let mut blocked = vec![false; normalized.len()];
+        for word in &self.words {
+            for (start, window) in normalized.windows(word.len()).enumerate() {
+                if window == word.as_slice() {
+                    blocked[start..start + word.len()].fill(true);
+                }
+            }
+        }
+        blocked
+    }
+}
+
+fn normalize(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    let c = match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => c,
+    };
+    LEETSPEAK
+        .iter()
+        .find(|(leet, _)| *leet == c)
+        .map_or(c, |&(_, letter)| letter)
+}
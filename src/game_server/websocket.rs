@@ -0,0 +1,91 @@
+use std::io::Write;
+
+use axum::extract::ws::Message;
+use flate2::{Compression as Level, write::DeflateEncoder};
+use serde::{Deserialize, Serialize};
+
+use super::room::PlayerMessage;
+
+// smaller messages barely shrink, so they aren't worth the cpu
+const COMPRESSION_THRESHOLD: usize = 256;
+
+/// how messages are serialized, picked by a client with `?format=`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// json in text frames.
+    #[default]
+    Json,
+    /// msgpack in binary frames, with structs as maps keyed by field name like the json.
+    Msgpack,
+}
+
+/// a compression scheme a client can ask for with `?compress=`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// raw deflate, as in rfc 1951, with no zlib or gzip header.
+    Deflate,
+}
+
+/// a frame that couldn't be turned into a `PlayerMessage`.
+#[derive(Debug)]
+pub struct DecodeError;
+
+/// how messages are framed for one connection.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Encoding {
+    format: Format,
+    compression: Option<Compression>,
+}
+
+impl Encoding {
+    /// compression only applies to json, where binary frames are otherwise unused and so can
+    /// mark a compressed message. msgpack is already compact.
+    pub fn new(format: Format, compression: Option<Compression>) -> Self {
+        Self {
+            format,
+            compression: compression.filter(|_| format == Format::Json),
+        }
+    }
+
+    /// json in a text frame, or deflated json in a binary frame if compression was negotiated
+    /// and the message is big enough to be worth it, or msgpack in a binary frame.
+    pub fn encode(self, message: &impl Serialize) -> Message {
+        if self.format == Format::Msgpack {
+            return Message::binary(
+                rmp_serde::to_vec_named(message).expect("serializing message failed"),
+            );
+        }
+
+        let json = serde_json::to_string(message).expect("serializing message failed");
+        match self.compression {
+            Some(Compression::Deflate) if json.len() >= COMPRESSION_THRESHOLD => {
+                Message::binary(deflate(json.as_bytes()))
+            }
+            _ => Message::text(json),
+        }
+    }
+
+    /// `None` for frames that aren't part of the protocol, like pings, which are answered for us.
+    pub fn decode(self, message: &Message) -> Option<Result<PlayerMessage, DecodeError>> {
+        match (self.format, message) {
+            (Format::Json, Message::Text(json)) => {
+                Some(serde_json::from_str(json.as_str()).map_err(|_| DecodeError))
+            }
+            (Format::Msgpack, Message::Binary(bytes)) => {
+                Some(rmp_serde::from_slice(bytes).map_err(|_| DecodeError))
+            }
+            (_, Message::Text(_) | Message::Binary(_)) => Some(Err(DecodeError)),
+            _ => None,
+        }
+    }
+}
+
+fn deflate(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Level::default());
+    encoder
+        .write_all(bytes)
+        .expect("writing to a vec can't fail");
+    encoder.finish().expect("writing to a vec can't fail")
+}
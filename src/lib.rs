@@ -0,0 +1,6 @@
+pub mod config;
+pub mod game;
+mod game_server;
+pub mod tls;
+
+pub use game_server::{Emote, GameServerHandle, PlayerMessage, ServerMessage, init_game_server};
@@ -1,43 +1,97 @@
-use axum::Router;
-use game_server::init_game_server;
-use std::net::SocketAddr;
+use axum::{Router, serve::ListenerExt};
+use rusty_robots::{
+    config::ServerConfig,
+    init_game_server,
+    tls::{self, TlsListener},
+};
+use std::{net::SocketAddr, time::Duration};
+use tokio::signal;
 use tower_http::{
     services::{ServeDir, ServeFile},
     trace::TraceLayer,
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-mod game_server;
-
 #[tokio::main]
 async fn main() {
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")).into()
+                format!(
+                    "{}=debug,tower_http=debug,game.outcome=info",
+                    env!("CARGO_CRATE_NAME")
+                )
+                .into()
             }),
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    let config = ServerConfig::from_env().expect("invalid server config");
+
+    let (game_server, handle) = init_game_server(&config);
+
     let app = if cfg!(feature = "client") {
         Router::new()
             .fallback_service(
                 ServeDir::new("client/build").fallback(ServeFile::new("client/build/dynamic.html")),
             )
-            .nest("/api", init_game_server())
+            .nest("/api", game_server)
     } else {
-        init_game_server()
+        game_server
+    }
+    .merge(handle.monitoring());
+
+    let listener = tokio::net::TcpListener::bind(config.addr).await.unwrap();
+    let app = app
+        .layer(TraceLayer::new_for_http())
+        .into_make_service_with_connect_info::<SocketAddr>();
+    let grace = Duration::from_secs(config.shutdown_grace_seconds);
+    let shutdown = async move {
+        shutdown_signal().await;
+        tracing::info!("shutting down");
+        handle.shutdown(grace).await;
+    };
+
+    match &config.tls {
+        Some(tls) => {
+            let acceptor = tls::acceptor(&tls.cert, &tls.key).expect("invalid tls config");
+            tracing::debug!("listening on https://{}", listener.local_addr().unwrap());
+            // tapping the listener is what lets axum hand out its addresses as connect info
+            let listener = TlsListener::new(listener, acceptor).tap_io(|_| ());
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+                .unwrap();
+        }
+        None => {
+            tracing::debug!("listening on http://{}", listener.local_addr().unwrap());
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+                .unwrap();
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c().await.unwrap();
     };
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3003));
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    tracing::debug!("listening on http://{}", listener.local_addr().unwrap());
-    axum::serve(listener, app.layer(TraceLayer::new_for_http()))
-        .with_graceful_shutdown((|| async {
-            tokio::signal::ctrl_c().await.unwrap();
-            println!("handling ctrlc");
-        })())
-        .await
-        .unwrap();
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
@@ -0,0 +1,111 @@
+use std::{io, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+use axum::serve::Listener;
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
+    time::{sleep, timeout},
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        self,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+    server::TlsStream,
+};
+
+/// how long a client gets to finish its handshake before it's dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("couldn't read {path}: {source}")]
+    Pem {
+        path: Arc<str>,
+        source: rustls::pki_types::pem::Error,
+    },
+    #[error("no certificates in {0}")]
+    NoCertificates(Arc<str>),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+}
+
+/// builds an acceptor from a pem certificate chain and private key.
+pub fn acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor, TlsError> {
+    let pem_error = |path: &Path| {
+        let path: Arc<str> = path.to_string_lossy().into();
+        move |source| TlsError::Pem { path, source }
+    };
+
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .map_err(pem_error(cert))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(cert.to_string_lossy().into()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key).map_err(pem_error(key))?;
+
+    let mut config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    // websockets upgrade over http/1.1, so don't offer h2
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// a tcp listener that hands out connections once their tls handshake is done. handshakes run
+/// concurrently, so a slow client doesn't hold up the rest.
+pub struct TlsListener {
+    tcp: TcpListener,
+    acceptor: TlsAcceptor,
+    handshakes: JoinSet<io::Result<(TlsStream<TcpStream>, SocketAddr)>>,
+}
+
+impl TlsListener {
+    pub fn new(tcp: TcpListener, acceptor: TlsAcceptor) -> Self {
+        Self {
+            tcp,
+            acceptor,
+            handshakes: JoinSet::new(),
+        }
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            tokio::select! {
+                accepted = self.tcp.accept() => match accepted {
+                    Ok((stream, addr)) => {
+                        let acceptor = self.acceptor.clone();
+                        self.handshakes.spawn(async move {
+                            let stream = timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                                .await
+                                .map_err(io::Error::from)??;
+                            Ok((stream, addr))
+                        });
+                    }
+                    Err(err) => {
+                        tracing::error!("accept failed: {err}");
+                        sleep(Duration::from_secs(1)).await;
+                    }
+                },
+                Some(done) = self.handshakes.join_next() => match done {
+                    Ok(Ok(connection)) => return connection,
+                    Ok(Err(err)) => tracing::debug!("tls handshake failed: {err}"),
+                    Err(err) => tracing::error!("tls handshake panicked: {err}"),
+                },
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.tcp.local_addr()
+    }
+}
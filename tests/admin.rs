@@ -0,0 +1,254 @@
+mod common;
+
+use axum::{Router, http::StatusCode};
+use common::*;
+use rusty_robots::config::ServerConfig;
+use serde_json::{Value, json};
+
+const ADMIN_TOKEN: &str = "let-me-in";
+
+fn admin_app() -> Router {
+    rusty_robots::init_game_server(&ServerConfig {
+        admin_token: Some(ADMIN_TOKEN.into()),
+        ..ServerConfig::default()
+    })
+    .0
+}
+
+#[tokio::test]
+async fn room_logs_keep_messages_in_order() {
+    let (app, addr) = serve_with(ServerConfig {
+        admin_token: Some(ADMIN_TOKEN.into()),
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    for text in ["one", "two", "three"] {
+        say(&mut host, json!({ "type": "chat", "text": text })).await;
+        expect(&mut host, "chat").await;
+    }
+
+    let (status, log) = get_with_token(
+        &app,
+        &format!("/admin/rooms/{code}/log"),
+        &json!(ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = log.as_array().unwrap();
+    let chats: Vec<&Value> = entries
+        .iter()
+        .filter(|entry| entry["message"]["type"] == "chat")
+        .map(|entry| &entry["message"]["text"])
+        .collect();
+    assert_eq!(chats, ["one", "two", "three"]);
+    assert!(
+        entries
+            .windows(2)
+            .all(|pair| pair[0]["at"].as_u64() <= pair[1]["at"].as_u64())
+    );
+    // the welcome went to the host alone
+    assert!(entries.iter().any(|entry| entry["to"] == "host"));
+}
+
+#[tokio::test]
+async fn countdowns_stay_out_of_room_logs() {
+    let (app, addr) = serve_with(ServerConfig {
+        admin_token: Some(ADMIN_TOKEN.into()),
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    say(&mut host, json!({ "type": "chat", "text": "good luck" })).await;
+    expect(&mut host, "chat").await;
+    say(&mut host, json!({ "type": "start" })).await;
+    for _ in 0..3 {
+        expect(&mut host, "time_remaining").await;
+    }
+
+    let (_, log) = get_with_token(
+        &app,
+        &format!("/admin/rooms/{code}/log"),
+        &json!(ADMIN_TOKEN),
+    )
+    .await;
+    let kinds: Vec<&Value> = log
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| &entry["message"]["type"])
+        .collect();
+    assert!(kinds.contains(&&json!("chat")), "{kinds:?}");
+    assert!(kinds.contains(&&json!("phase_changed")), "{kinds:?}");
+    assert!(!kinds.contains(&&json!("time_remaining")), "{kinds:?}");
+}
+
+#[tokio::test]
+async fn room_logs_need_the_admin_token() {
+    let app = admin_app();
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let uri = format!("/admin/rooms/{}/log", created["code"].as_str().unwrap());
+
+    let (status, body) = get(&app, &uri).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "missing_token");
+
+    // a player's token is no good here
+    let (status, _) = get_with_token(&app, &uri, &created["token"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // no admin token configured at all
+    let app = common::app();
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let uri = format!("/admin/rooms/{}/log", created["code"].as_str().unwrap());
+    let (status, body) = get_with_token(&app, &uri, &json!(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], "admin_disabled");
+}
+
+#[tokio::test]
+async fn room_state_leaves_out_secrets() {
+    let (app, addr) = serve_with(ServerConfig {
+        admin_token: Some(ADMIN_TOKEN.into()),
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "password": "hunter22" }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie", "password": "hunter22" }),
+    )
+    .await;
+    let _host = connect(addr, &created["code"], &created["token"]).await;
+    let uri = format!("/admin/rooms/{code}/state");
+
+    let (status, _) = get(&app, &uri).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, state) = get_with_token(&app, &uri, &json!(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state["code"], code);
+    assert_eq!(state["host"], "host");
+    assert_eq!(state["requires_password"], true);
+    let mut players = state["players"].as_array().unwrap().clone();
+    players.sort_by_key(|player| player["username"].as_str().unwrap().to_owned());
+    assert_eq!(players[0]["username"], "host");
+    assert_eq!(players[0]["connected"], true);
+    assert!(players[0]["connection"].is_u64());
+    assert_eq!(players[1]["username"], "robbie");
+    assert_eq!(players[1]["points"], 0);
+    assert_eq!(players[1]["connected"], false);
+
+    let dump = state.to_string();
+    for secret in [&created["token"], &joined["token"]] {
+        assert!(!dump.contains(secret.as_str().unwrap()));
+    }
+    assert!(!dump.contains("hunter22"));
+    assert!(state.get("tokens").is_none());
+}
+
+#[tokio::test]
+async fn stats_count_rooms_players_and_games() {
+    let (app, addr) = serve_with(ServerConfig {
+        admin_token: Some(ADMIN_TOKEN.into()),
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "rounds": 1 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    let (status, _) = get(&app, "/admin/stats").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, stats) = get_with_token(&app, "/admin/stats", &json!(ADMIN_TOKEN)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stats["rooms"], 1);
+    assert_eq!(stats["players"], 2);
+    assert_eq!(stats["connected_players"], 1);
+    assert_eq!(stats["rooms_created"], 1);
+    assert_eq!(stats["players_joined"], 1);
+    assert_eq!(stats["games_started"], 0);
+    assert!(stats["uptime_seconds"].is_u64());
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    expect(&mut host, "phase_changed").await;
+    say(&mut host, json!({ "type": "pass" })).await;
+    expect(&mut host, "game_over").await;
+
+    let (_, stats) = get_with_token(&app, "/admin/stats", &json!(ADMIN_TOKEN)).await;
+    assert_eq!(stats["games_started"], 1);
+    assert_eq!(stats["games_finished"], 1);
+}
+
+#[tokio::test]
+async fn room_logs_count_who_broadcasts_reached() {
+    let (app, addr) = serve_with(ServerConfig {
+        admin_token: Some(ADMIN_TOKEN.into()),
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    for username in ["robbie", "roberta"] {
+        post_ok(
+            &app,
+            &format!("/rooms/{code}/join"),
+            json!({ "username": username }),
+        )
+        .await;
+    }
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    say(&mut host, json!({ "type": "chat", "text": "anyone?" })).await;
+    expect(&mut host, "chat").await;
+
+    let (_, log) = get_with_token(
+        &app,
+        &format!("/admin/rooms/{code}/log"),
+        &json!(ADMIN_TOKEN),
+    )
+    .await;
+    let chat = log
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["message"]["type"] == "chat")
+        .unwrap();
+    assert_eq!(
+        chat["delivery"],
+        json!({ "delivered": 1, "skipped_disconnected": 2, "failed": 0 })
+    );
+    // messages to one player aren't counted
+    let welcome = log
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["to"] == "host")
+        .unwrap();
+    assert!(welcome.get("delivery").is_none());
+}
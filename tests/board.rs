@@ -0,0 +1,310 @@
+use proptest::{collection, prelude::*, sample::select};
+use rand::{SeedableRng, rngs::StdRng};
+use rusty_robots::game::{
+    BOARD_SIZE, BOARD_SIZES, Board, Direction, Position, Robot, Target, TargetKind,
+};
+
+/// a board of `size` cells with arbitrary walls and the robots on distinct cells. walls and
+/// robots shrink independently, so a failing case reduces to the few pieces that matter.
+fn board() -> impl Strategy<Value = Board> {
+    (4..=12u8).prop_flat_map(|size| {
+        let cell = (0..size, 0..size).prop_map(|(x, y)| Position::new(x, y));
+        let walls = collection::vec((cell.clone(), select(Direction::ALL.to_vec())), 0..64);
+        let robots = collection::hash_set(cell, Robot::ALL.len()).prop_map(|robots| {
+            let robots: Vec<_> = robots.into_iter().collect();
+            <[Position; 4]>::try_from(robots).unwrap()
+        });
+
+        (walls, robots).prop_map(move |(walls, robots)| {
+            let mut board = Board::new(size);
+            for (position, direction) in walls {
+                board.add_wall(position, direction);
+            }
+            board.place_robots(robots);
+            board
+        })
+    })
+}
+
+/// the cell next to `position` in `direction`, ignoring walls.
+fn next(board: &Board, position: Position, direction: Direction) -> Option<Position> {
+    let (x, y) = match direction {
+        Direction::Up => (Some(position.x), position.y.checked_sub(1)),
+        Direction::Down => (Some(position.x), position.y.checked_add(1)),
+        Direction::Left => (position.x.checked_sub(1), Some(position.y)),
+        Direction::Right => (position.x.checked_add(1), Some(position.y)),
+    };
+    let (x, y) = (x?, y?);
+    (x < board.size() && y < board.size()).then_some(Position::new(x, y))
+}
+
+fn occupied(board: &Board, position: Position) -> bool {
+    board.robots().contains(&position)
+}
+
+proptest! {
+    #[test]
+    fn slides_never_cross_walls_or_robots(
+        board in board(),
+        robot in select(Robot::ALL.to_vec()),
+        direction in select(Direction::ALL.to_vec()),
+    ) {
+        let start = board.robot(robot);
+        let end = board.slide(robot, direction);
+
+        let mut position = start;
+        while position != end {
+            prop_assert!(!board.has_wall(position, direction), "crossed a wall at {position:?}");
+            position = next(&board, position, direction).expect("slid off the board");
+            prop_assert!(!occupied(&board, position), "passed through a robot at {position:?}");
+        }
+    }
+
+    #[test]
+    fn slides_stop_only_when_blocked(
+        board in board(),
+        robot in select(Robot::ALL.to_vec()),
+        direction in select(Direction::ALL.to_vec()),
+    ) {
+        let end = board.slide(robot, direction);
+
+        let blocked = board.has_wall(end, direction)
+            || next(&board, end, direction).is_none_or(|next| occupied(&board, next));
+        prop_assert!(blocked, "stopped early at {end:?}");
+    }
+
+    #[test]
+    fn slides_are_idempotent(
+        mut board in board(),
+        robot in select(Robot::ALL.to_vec()),
+        direction in select(Direction::ALL.to_vec()),
+    ) {
+        let end = board.slide(robot, direction);
+        board.place_robot(robot, end);
+
+        prop_assert_eq!(board.slide(robot, direction), end);
+    }
+}
+
+fn red_to(x: u8, y: u8) -> Target {
+    Target {
+        kind: TargetKind::Robot(Robot::Red),
+        position: Position::new(x, y),
+    }
+}
+
+/// plays `path` out on a copy of the board, returning where the robots end up.
+fn play(board: &Board, path: &[(Robot, Direction)]) -> Board {
+    let mut board = board.clone();
+    for &(robot, direction) in path {
+        let to = board.slide(robot, direction);
+        board.place_robot(robot, to);
+    }
+    board
+}
+
+#[test]
+fn reached_targets_take_no_moves() {
+    let board = Board::new(4);
+    assert_eq!(board.min_moves(red_to(0, 0)), Some(0));
+}
+
+#[test]
+fn straight_slides_take_one_move() {
+    let board = Board::new(4);
+    assert_eq!(board.min_moves(red_to(0, 3)), Some(1));
+}
+
+#[test]
+fn other_robots_can_be_used_as_blockers() {
+    // red has to slide along the bottom, and needs blue parked at (2, 3) to stop at (1, 3)
+    let board = Board::new(4);
+    let target = red_to(1, 3);
+    assert_eq!(board.min_moves(target), Some(3));
+
+    let path = board.solve(target).unwrap();
+    assert_eq!(play(&board, &path).robot(Robot::Red), target.position);
+}
+
+#[test]
+fn walls_can_stop_a_slide() {
+    let mut board = Board::new(4);
+    board.add_wall(Position::new(0, 2), Direction::Down);
+    assert_eq!(board.min_moves(red_to(0, 2)), Some(1));
+    assert_eq!(board.min_moves(red_to(0, 3)), Some(4));
+}
+
+#[test]
+fn walled_off_targets_have_no_solution() {
+    let mut board = Board::new(4);
+    for direction in Direction::ALL {
+        board.add_wall(Position::new(2, 2), direction);
+    }
+    assert_eq!(board.min_moves(red_to(2, 2)), None);
+}
+
+#[test]
+fn small_boards_are_laid_out_within_their_edges() {
+    for seed in 0..64 {
+        let board = Board::generate(8, &mut StdRng::seed_from_u64(seed));
+        assert_eq!(board.size(), 8);
+
+        // two targets per robot, each tucked into a corner of walls, all on distinct cells
+        let targets = board.targets();
+        assert_eq!(targets.len(), 8);
+        for robot in Robot::ALL {
+            assert_eq!(
+                targets
+                    .iter()
+                    .filter(|target| target.kind == TargetKind::Robot(robot))
+                    .count(),
+                2
+            );
+        }
+        for target in targets {
+            let Position { x, y } = target.position;
+            assert!(x < 8 && y < 8, "target off the board at {x}, {y}");
+            assert!(
+                !((3..=4).contains(&x) && (3..=4).contains(&y)),
+                "target in the centre"
+            );
+            let vertical = board.has_wall(target.position, Direction::Up)
+                || board.has_wall(target.position, Direction::Down);
+            let horizontal = board.has_wall(target.position, Direction::Left)
+                || board.has_wall(target.position, Direction::Right);
+            assert!(
+                vertical && horizontal,
+                "target at {x}, {y} isn't in a corner"
+            );
+        }
+
+        let robots = board.robots();
+        for (i, robot) in robots.iter().enumerate() {
+            assert!(robot.x < 8 && robot.y < 8);
+            assert!(!robots[..i].contains(robot));
+            assert!(!targets.iter().any(|target| target.position == *robot));
+        }
+
+        // sliding anything anywhere stops at the smaller edge at the furthest
+        for robot in Robot::ALL {
+            for direction in Direction::ALL {
+                let end = board.slide(robot, direction);
+                assert!(end.x < 8 && end.y < 8, "slid off to {end:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn slides_stop_at_the_edge_of_a_small_board() {
+    let board = Board::new(8);
+    let mut moved = board.clone();
+    moved.place_robot(Robot::Yellow, Position::new(3, 4));
+    assert_eq!(
+        moved.slide(Robot::Yellow, Direction::Right),
+        Position::new(7, 4)
+    );
+    assert_eq!(
+        moved.slide(Robot::Yellow, Direction::Down),
+        Position::new(3, 7)
+    );
+    assert_eq!(
+        board.slide(Robot::Red, Direction::Down),
+        Position::new(0, 7)
+    );
+}
+
+#[test]
+fn every_supported_size_can_be_generated() {
+    for size in BOARD_SIZES {
+        let board = Board::generate(size, &mut StdRng::seed_from_u64(7));
+        assert_eq!(board.size(), size);
+        // with a wildcard on top on a full size board
+        let wildcards = usize::from(size == BOARD_SIZE);
+        assert_eq!(board.targets().len(), size as usize + wildcards);
+        assert_eq!(
+            board
+                .targets()
+                .iter()
+                .filter(|target| target.kind == TargetKind::Wildcard)
+                .count(),
+            wildcards
+        );
+    }
+}
+
+#[test]
+fn wildcards_are_reached_by_any_robot() {
+    // yellow starts above it, so it only takes one slide down. red would need two
+    let board = Board::new(4);
+    let wildcard = Target {
+        kind: TargetKind::Wildcard,
+        position: Position::new(3, 3),
+    };
+    assert_eq!(board.min_moves(wildcard), Some(1));
+    assert_eq!(
+        board.solve(wildcard).unwrap(),
+        [(Robot::Yellow, Direction::Down)]
+    );
+    assert_eq!(board.min_moves(red_to(3, 3)), Some(2));
+
+    // already on it counts, whichever robot it is
+    let under = Target {
+        kind: TargetKind::Wildcard,
+        position: Position::new(2, 0),
+    };
+    assert_eq!(board.min_moves(under), Some(0));
+}
+
+#[test]
+fn colored_targets_only_count_their_own_robot() {
+    // blue sits on the target, but that doesn't win it for red
+    let board = Board::new(4);
+    let target = red_to(2, 0);
+    assert_ne!(board.min_moves(target), Some(0));
+    let path = board.solve(target).unwrap();
+    assert_eq!(play(&board, &path).robot(Robot::Red), target.position);
+}
+
+/// every cell `robot` can stop on by itself, with the others parked in the centre.
+fn stops(board: &Board, robot: Robot) -> Vec<Position> {
+    let mut board = board.clone();
+    let half = board.size() / 2;
+    let centre = [(half - 1, half - 1), (half, half - 1), (half - 1, half)];
+    let others = Robot::ALL.into_iter().filter(|&other| other != robot);
+    for (other, (x, y)) in others.zip(centre) {
+        board.place_robot(other, Position::new(x, y));
+    }
+
+    let mut seen = vec![board.robot(robot)];
+    let mut index = 0;
+    while let Some(&position) = seen.get(index) {
+        board.place_robot(robot, position);
+        for direction in Direction::ALL {
+            let to = board.slide(robot, direction);
+            if !seen.contains(&to) {
+                seen.push(to);
+            }
+        }
+        index += 1;
+    }
+    seen
+}
+
+#[test]
+fn generated_targets_are_all_reachable() {
+    for size in BOARD_SIZES {
+        for seed in 0..32 {
+            let board = Board::generate(size, &mut StdRng::seed_from_u64(seed));
+            for &target in board.targets() {
+                assert!(
+                    Robot::ALL
+                        .into_iter()
+                        .filter(|&robot| target.kind.accepts(robot))
+                        .any(|robot| stops(&board, robot).contains(&target.position)),
+                    "{target:?} sealed off on a board of {size} from seed {seed}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{collect_until, connect, expect, post_json_with_token, post_ok, say, serve};
+use serde_json::json;
+use tokio_tungstenite::connect_async;
+
+#[tokio::test]
+async fn bots_bid_and_solve() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": 7, "rounds": 1 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    let (status, added) = post_json_with_token(
+        &app,
+        &format!("/rooms/{code}/bots"),
+        &created["token"],
+        json!({ "handicap": 1 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let bot = &added["username"];
+    assert_eq!(expect(&mut host, "join").await["username"], *bot);
+
+    say(&mut host, json!({ "type": "start" })).await;
+    let bid = expect(&mut host, "bid_placed").await;
+    assert_eq!(bid["username"], *bot);
+    say(&mut host, json!({ "type": "pass" })).await;
+
+    let solution = expect(&mut host, "solution").await;
+    let optimal = solution["optimal_moves"].as_u64().unwrap();
+    assert_eq!(bid["moves"], optimal + 1);
+
+    let over = expect(&mut host, "game_over").await;
+    assert_eq!(over["standings"][0]["username"], *bot);
+    assert_eq!(over["standings"][0]["points"], 1);
+}
+
+#[tokio::test]
+async fn bots_play_on_the_board_the_settings_dealt() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": 7 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    let (status, added) = post_json_with_token(
+        &app,
+        &format!("/rooms/{code}/bots"),
+        &created["token"],
+        json!({ "handicap": 1 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let bot = &added["username"];
+
+    let (status, _) = post_json_with_token(
+        &app,
+        &format!("/rooms/{code}/settings"),
+        &created["token"],
+        json!({ "rounds": 1, "board_size": 8 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let changed = expect(&mut host, "settings_changed").await;
+    assert_eq!(changed["board"]["size"], 8);
+
+    say(&mut host, json!({ "type": "start" })).await;
+    let bid = expect(&mut host, "bid_placed").await;
+    assert_eq!(bid["username"], *bot);
+    say(&mut host, json!({ "type": "pass" })).await;
+
+    let solution = expect(&mut host, "solution").await;
+    let optimal = solution["optimal_moves"].as_u64().unwrap();
+    assert_eq!(bid["moves"], optimal + 1);
+
+    let over = expect(&mut host, "game_over").await;
+    assert_eq!(over["standings"][0]["username"], *bot);
+    assert_eq!(over["standings"][0]["points"], 1);
+}
+
+#[tokio::test]
+async fn only_the_host_adds_bots() {
+    let (app, _) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    let (status, body) = post_json_with_token(
+        &app,
+        &format!("/rooms/{code}/bots"),
+        &joined["token"],
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "not_host");
+}
+
+#[tokio::test]
+async fn a_burst_of_bot_moves_comes_out_as_one_board_state() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": 7, "rounds": 1 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let (mut spectator, _) = connect_async(format!(
+        "ws://{addr}/rooms/{code}/spectate?coalesce_moves=true"
+    ))
+    .await
+    .unwrap();
+    post_json_with_token(
+        &app,
+        &format!("/rooms/{code}/bots"),
+        &created["token"],
+        json!({}),
+    )
+    .await;
+
+    say(&mut host, json!({ "type": "start" })).await;
+    expect(&mut host, "bid_placed").await;
+    say(&mut host, json!({ "type": "pass" })).await;
+
+    // the host still sees every move
+    let seen = collect_until(&mut host, "round_over").await;
+    let moves = seen
+        .iter()
+        .filter(|message| message["type"] == "robot_moved")
+        .count();
+    assert!(moves > 1, "only {moves} moves");
+    assert!(!seen.iter().any(|message| message["type"] == "board_state"));
+
+    let watched = collect_until(&mut spectator, "round_over").await;
+    assert!(
+        !watched
+            .iter()
+            .any(|message| message["type"] == "robot_moved")
+    );
+    let states: Vec<_> = watched
+        .iter()
+        .filter(|message| message["type"] == "board_state")
+        .collect();
+    assert_eq!(states.len(), 1);
+    let last = seen
+        .iter()
+        .rfind(|message| message["type"] == "robot_moved")
+        .unwrap();
+    assert!(
+        states[0]["robots"]
+            .as_array()
+            .unwrap()
+            .contains(&last["to"])
+    );
+}
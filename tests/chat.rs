@@ -0,0 +1,262 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    connect, expect, expect_any, open, open_with_query, post_json, post_ok, say, serve, serve_with,
+};
+use rusty_robots::config::ServerConfig;
+use serde_json::json;
+
+const WORD_LIST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/words.txt");
+
+fn filtered() -> ServerConfig {
+    ServerConfig {
+        word_list: Some(WORD_LIST.into()),
+        ..ServerConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn emotes_are_broadcast() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &joined["token"]).await;
+
+    say(&mut robbie, json!({ "type": "emote", "kind": "laugh" })).await;
+    let emote = expect(&mut host, "emote").await;
+    assert_eq!(emote["username"], "robbie");
+    assert_eq!(emote["kind"], "laugh");
+}
+
+#[tokio::test]
+async fn chat_and_emotes_share_a_rate_limit() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    for _ in 0..3 {
+        say(&mut host, json!({ "type": "chat", "text": "hi" })).await;
+        say(&mut host, json!({ "type": "emote", "kind": "clap" })).await;
+    }
+
+    let error = expect(&mut host, "error").await;
+    assert_eq!(error["code"], "rate_limited");
+}
+
+#[tokio::test]
+async fn disconnecting_clears_typing() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &joined["token"]).await;
+
+    say(&mut robbie, json!({ "type": "typing", "active": true })).await;
+    let typing = expect(&mut host, "typing").await;
+    assert_eq!(typing["username"], "robbie");
+    assert_eq!(typing["active"], true);
+
+    robbie.close(None).await.unwrap();
+    let typing = expect(&mut host, "typing").await;
+    assert_eq!(typing["username"], "robbie");
+    assert_eq!(typing["active"], false);
+    expect(&mut host, "disconnect").await;
+}
+
+#[tokio::test]
+async fn whispers_reach_only_their_target() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let uri = format!("/rooms/{}/join", code.as_str().unwrap());
+    let robbie = post_ok(&app, &uri, json!({ "username": "robbie" })).await;
+    let roberta = post_ok(&app, &uri, json!({ "username": "roberta" })).await;
+
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &robbie["token"]).await;
+    let mut roberta = connect(addr, code, &roberta["token"]).await;
+
+    say(
+        &mut host,
+        json!({ "type": "whisper", "to": "robbie", "text": " psst " }),
+    )
+    .await;
+    let whisper = expect(&mut robbie, "whisper").await;
+    assert_eq!(whisper["from"], "host");
+    assert_eq!(whisper["text"], "psst");
+    let copy = expect(&mut host, "whisper").await;
+    assert_eq!(copy["to"], "robbie");
+
+    // roberta only ever sees the chat that follows
+    say(&mut host, json!({ "type": "chat", "text": "hi all" })).await;
+    let next = expect_any(&mut roberta, &["whisper", "chat"]).await;
+    assert_eq!(next["type"], "chat");
+}
+
+#[tokio::test]
+async fn whispering_a_missing_player_fails() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    say(
+        &mut host,
+        json!({ "type": "whisper", "to": "nobody", "text": "psst" }),
+    )
+    .await;
+    let error = expect(&mut host, "error").await;
+    assert_eq!(error["code"], "player_not_found");
+}
+
+#[tokio::test]
+async fn blocked_words_are_masked_in_chat() {
+    let (app, addr) = serve_with(filtered()).await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    say(
+        &mut host,
+        json!({ "type": "chat", "text": "what the H3CK, darnit" }),
+    )
+    .await;
+    assert_eq!(
+        expect(&mut host, "chat").await["text"],
+        "what the ****, ****it"
+    );
+}
+
+#[tokio::test]
+async fn blocked_words_are_kept_out_of_usernames() {
+    let (app, _) = serve_with(filtered()).await;
+
+    let (status, body) = post_json(&app, "/rooms/create", json!({ "username": "xXD4rnXx" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_username");
+
+    post_ok(&app, "/rooms/create", json!({ "username": "robbie" })).await;
+}
+
+#[tokio::test]
+async fn authors_can_edit_and_delete_their_chat() {
+    let (app, addr) = serve_with(filtered()).await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &joined["token"]).await;
+
+    say(&mut robbie, json!({ "type": "chat", "text": "hi" })).await;
+    let chat = expect(&mut host, "chat").await;
+    let id = chat["id"].clone();
+
+    say(
+        &mut robbie,
+        json!({ "type": "chat_edit", "id": id, "text": "oh heck" }),
+    )
+    .await;
+    let edited = expect(&mut host, "chat_edited").await;
+    assert_eq!(edited["id"], id);
+    assert_eq!(edited["text"], "oh ****");
+
+    say(&mut robbie, json!({ "type": "chat_delete", "id": id })).await;
+    assert_eq!(expect(&mut host, "chat_deleted").await["id"], id);
+
+    say(&mut robbie, json!({ "type": "chat_delete", "id": id })).await;
+    let error = expect(&mut robbie, "error").await;
+    assert_eq!(error["code"], "chat_not_found");
+}
+
+#[tokio::test]
+async fn only_the_author_can_edit_a_chat() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &joined["token"]).await;
+
+    say(&mut robbie, json!({ "type": "chat", "text": "hi" })).await;
+    let id = expect(&mut host, "chat").await["id"].clone();
+    expect(&mut robbie, "chat").await;
+
+    say(
+        &mut host,
+        json!({ "type": "chat_edit", "id": id, "text": "bye" }),
+    )
+    .await;
+    let error = expect(&mut host, "error").await;
+    assert_eq!(error["code"], "not_author");
+    say(&mut host, json!({ "type": "chat_delete", "id": id })).await;
+    let error = expect(&mut host, "error").await;
+    assert_eq!(error["code"], "not_author");
+
+    // robbie's chat was left alone
+    say(&mut robbie, json!({ "type": "chat", "text": "still here" })).await;
+    let next = expect_any(&mut robbie, &["chat_edited", "chat_deleted", "chat"]).await;
+    assert_eq!(next["text"], "still here");
+}
+
+#[tokio::test]
+async fn connecting_catches_up_on_chat() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let mut host = connect(addr, code, &created["token"]).await;
+    say(&mut host, json!({ "type": "chat", "text": "one" })).await;
+    let first = expect(&mut host, "chat").await["id"].clone();
+    say(&mut host, json!({ "type": "chat", "text": "two" })).await;
+    expect(&mut host, "chat").await;
+    say(
+        &mut host,
+        json!({ "type": "chat_edit", "id": first, "text": "uno" }),
+    )
+    .await;
+    expect(&mut host, "chat_edited").await;
+
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut robbie = open(addr, code, &joined["token"]).await;
+    expect(&mut robbie, "welcome").await;
+    let history = expect(&mut robbie, "chat_history").await;
+    let chats = history["chats"].as_array().unwrap();
+    assert_eq!(chats.len(), 2);
+    assert_eq!(chats[0]["username"], "host");
+    assert_eq!(chats[0]["text"], "uno");
+    assert_eq!(chats[1]["text"], "two");
+    drop(robbie);
+
+    // coming back with the last chat seen only catches up on the ones after it
+    expect(&mut host, "disconnect").await;
+    let mut robbie = open_with_query(addr, code, &joined["token"], &format!("since={first}")).await;
+    expect(&mut robbie, "welcome").await;
+    let history = expect(&mut robbie, "chat_history").await;
+    assert_eq!(history["chats"], json!([chats[1]]));
+}
@@ -0,0 +1,219 @@
+//! helpers shared by the integration tests: oneshot requests against the router, and
+//! websocket clients against a server bound to a local port.
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, time::Duration};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use futures_util::{SinkExt, StreamExt};
+use rusty_robots::{config::ServerConfig, init_game_server};
+use serde_json::Value;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, connect_async,
+    tungstenite::{Message, client::IntoClientRequest},
+};
+use tower::ServiceExt;
+
+pub type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub fn app() -> Router {
+    init_game_server(&ServerConfig::default()).0
+}
+
+pub async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&body).unwrap()
+    };
+    (status, body)
+}
+
+pub async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+/// gets `uri` as the owner of `token`.
+pub async fn get_with_token(app: &Router, uri: &str, token: &Value) -> (StatusCode, Value) {
+    send(
+        app,
+        Request::get(uri)
+            .header(
+                "Authorization",
+                format!("Bearer {}", token.as_str().unwrap()),
+            )
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+}
+
+pub async fn post_json(app: &Router, uri: &str, body: Value) -> (StatusCode, Value) {
+    send(
+        app,
+        Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+    )
+    .await
+}
+
+/// posts `body`, expecting it to succeed.
+pub async fn post_ok(app: &Router, uri: &str, body: Value) -> Value {
+    let (status, body) = post_json(app, uri, body).await;
+    assert_eq!(status, StatusCode::OK, "{uri} failed: {body}");
+    body
+}
+
+pub async fn post_with_token(app: &Router, uri: &str, token: &Value) -> (StatusCode, Value) {
+    send(
+        app,
+        Request::post(uri)
+            .header(
+                "Authorization",
+                format!("Bearer {}", token.as_str().unwrap()),
+            )
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+}
+
+/// posts `body` as the owner of `token`.
+pub async fn post_json_with_token(
+    app: &Router,
+    uri: &str,
+    token: &Value,
+    body: Value,
+) -> (StatusCode, Value) {
+    send(
+        app,
+        Request::post(uri)
+            .header("Content-Type", "application/json")
+            .header(
+                "Authorization",
+                format!("Bearer {}", token.as_str().unwrap()),
+            )
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+    )
+    .await
+}
+
+pub async fn serve() -> (Router, SocketAddr) {
+    serve_with(ServerConfig::default()).await
+}
+
+pub async fn serve_with(config: ServerConfig) -> (Router, SocketAddr) {
+    let (app, _) = init_game_server(&config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = app.clone();
+    tokio::spawn(async move {
+        let server = server.into_make_service_with_connect_info::<SocketAddr>();
+        axum::serve(listener, server).await.unwrap()
+    });
+    (app, addr)
+}
+
+/// connects to the room as the owner of `token`, once the room has welcomed them.
+pub async fn connect(addr: SocketAddr, code: &Value, token: &Value) -> Socket {
+    let mut socket = open(addr, code, token).await;
+    expect(&mut socket, "welcome").await;
+    socket
+}
+
+pub async fn open(addr: SocketAddr, code: &Value, token: &Value) -> Socket {
+    open_with_query(addr, code, token, "").await
+}
+
+/// like `open`, with `query` appended to the websocket url.
+pub async fn open_with_query(addr: SocketAddr, code: &Value, token: &Value, query: &str) -> Socket {
+    let mut request = format!("ws://{addr}/rooms/{}/ws?{query}", code.as_str().unwrap())
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        "Authorization",
+        format!("Bearer {}", token.as_str().unwrap())
+            .parse()
+            .unwrap(),
+    );
+
+    connect_async(request).await.unwrap().0
+}
+
+pub async fn say(socket: &mut Socket, message: Value) {
+    socket
+        .send(Message::text(message.to_string()))
+        .await
+        .unwrap();
+}
+
+/// skips messages until one of type `kind` arrives.
+pub async fn expect(socket: &mut Socket, kind: &str) -> Value {
+    expect_any(socket, &[kind]).await
+}
+
+/// skips messages until one of any of the `kinds` arrives.
+pub async fn expect_any(socket: &mut Socket, kinds: &[&str]) -> Value {
+    timeout(Duration::from_secs(10), async {
+        loop {
+            let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+                continue;
+            };
+            let message: Value = serde_json::from_str(&text).unwrap();
+            if kinds.iter().any(|kind| message["type"] == *kind) {
+                return message;
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no {kinds:?} message"))
+}
+
+/// every message up to and including the first of type `kind`.
+pub async fn collect_until(socket: &mut Socket, kind: &str) -> Vec<Value> {
+    let mut messages = Vec::new();
+    timeout(Duration::from_secs(10), async {
+        loop {
+            let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+                continue;
+            };
+            let message: Value = serde_json::from_str(&text).unwrap();
+            let done = message["type"] == kind;
+            messages.push(message);
+            if done {
+                return;
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no {kind} message"));
+    messages
+}
+
+/// skips messages until the socket is closed, returning the close code.
+pub async fn expect_close(socket: &mut Socket) -> u16 {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            if let Message::Close(frame) = socket.next().await.unwrap().unwrap() {
+                return frame.unwrap().code.into();
+            }
+        }
+    })
+    .await
+    .expect("socket not closed")
+}
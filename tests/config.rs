@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use rusty_robots::config::{ConfigError, ServerConfig};
+
+/// a config read from `vars` alone, as if they were the whole environment.
+fn from(vars: &[(&str, &str)]) -> Result<ServerConfig, ConfigError> {
+    let vars: HashMap<_, _> = vars.iter().copied().collect();
+    ServerConfig::from_vars(|var| vars.get(var).map(|value| value.to_string()))
+}
+
+#[test]
+fn unset_variables_fall_back_to_the_defaults() {
+    let config = from(&[]).unwrap();
+    let default = ServerConfig::default();
+    assert_eq!(config.addr, default.addr);
+    assert_eq!(config.channel_capacity, default.channel_capacity);
+    assert_eq!(config.max_rooms, default.max_rooms);
+    assert!(config.tls.is_none());
+}
+
+#[test]
+fn set_variables_are_parsed() {
+    let config = from(&[
+        ("RUSTY_ROBOTS_PORT", "8080"),
+        ("RUSTY_ROBOTS_CHANNEL_CAPACITY", "32"),
+        (
+            "RUSTY_ROBOTS_CORS_ORIGINS",
+            "https://a.example,https://b.example",
+        ),
+        ("RUSTY_ROBOTS_SECURE_COOKIES", "false"),
+    ])
+    .unwrap();
+    assert_eq!(config.addr.port(), 8080);
+    assert_eq!(config.channel_capacity, 32);
+    assert_eq!(config.cors_origins.len(), 2);
+    assert!(!config.secure_cookies);
+}
+
+#[test]
+fn channels_too_small_to_connect_through_are_refused() {
+    assert!(matches!(
+        from(&[("RUSTY_ROBOTS_CHANNEL_CAPACITY", "3")]),
+        Err(ConfigError::TooSmall { min: 8, .. })
+    ));
+    assert_eq!(
+        from(&[("RUSTY_ROBOTS_CHANNEL_CAPACITY", "8")])
+            .unwrap()
+            .channel_capacity,
+        8
+    );
+}
+
+#[test]
+fn bad_values_are_reported() {
+    assert!(matches!(
+        from(&[("RUSTY_ROBOTS_CHANNEL_CAPACITY", "lots")]),
+        Err(ConfigError::InvalidValue { value, .. }) if value == "lots"
+    ));
+    assert!(matches!(
+        from(&[("RUSTY_ROBOTS_TLS_CERT", "cert.pem")]),
+        Err(ConfigError::Unpaired(..))
+    ));
+}
@@ -0,0 +1,132 @@
+use std::{env, fs};
+
+use rusty_robots::{Emote, PlayerMessage, ServerMessage};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::json;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/messages.json");
+
+// compared as json, since fields like phase deadlines are never sent and can't survive the trip
+fn assert_round_trips<T>(messages: Vec<T>)
+where
+    T: Serialize + DeserializeOwned,
+{
+    for message in messages {
+        let json = serde_json::to_string(&message).unwrap();
+        let parsed: T = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+}
+
+fn assert_msgpack_round_trips<T>(messages: Vec<T>)
+where
+    T: Serialize + DeserializeOwned,
+{
+    for message in messages {
+        let json = serde_json::to_string(&message).unwrap();
+        let bytes = rmp_serde::to_vec_named(&message).unwrap();
+        let parsed: T = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+}
+
+#[test]
+fn messages_round_trip() {
+    assert_round_trips(PlayerMessage::examples());
+    assert_round_trips(ServerMessage::examples());
+}
+
+#[test]
+fn messages_round_trip_through_msgpack() {
+    assert_msgpack_round_trips(PlayerMessage::examples());
+    assert_msgpack_round_trips(ServerMessage::examples());
+}
+
+/// after a deliberate protocol change, regenerate the fixture with
+/// `UPDATE_FIXTURES=1 cargo test --test messages`.
+#[test]
+fn messages_match_fixture() {
+    let examples = json!({
+        "player": PlayerMessage::examples(),
+        "server": ServerMessage::examples(),
+    });
+    let expected = serde_json::to_string_pretty(&examples).unwrap() + "\n";
+
+    if env::var_os("UPDATE_FIXTURES").is_some() {
+        fs::write(FIXTURE, &expected).unwrap();
+    }
+    assert_eq!(fs::read_to_string(FIXTURE).unwrap(), expected);
+}
+
+#[test]
+fn emotes_are_a_fixed_set() {
+    let message: PlayerMessage =
+        serde_json::from_value(json!({ "type": "emote", "kind": "thumbsup" })).unwrap();
+    assert!(matches!(
+        message,
+        PlayerMessage::Emote {
+            kind: Emote::Thumbsup
+        }
+    ));
+
+    let unknown =
+        serde_json::from_value::<PlayerMessage>(json!({ "type": "emote", "kind": "poop" }));
+    assert!(unknown.is_err());
+}
+
+#[test]
+fn messages_are_tagged_by_type() {
+    let shapes = [
+        json!({ "type": "chat", "text": "hi" }),
+        json!({ "type": "bid", "moves": 5 }),
+        json!({ "type": "ping", "nonce": 7 }),
+        json!({ "type": "resync" }),
+    ];
+    for shape in shapes {
+        let message: PlayerMessage = serde_json::from_value(shape.clone()).unwrap();
+        assert_eq!(serde_json::to_value(message).unwrap(), shape);
+    }
+
+    let messages = [
+        ServerMessage::Join {
+            username: "robbie".into(),
+        },
+        ServerMessage::Chat {
+            id: 1,
+            username: "robbie".into(),
+            text: "hi".into(),
+        },
+        ServerMessage::Pong { nonce: 7 },
+    ];
+    let shapes = [
+        json!({ "type": "join", "username": "robbie" }),
+        json!({ "type": "chat", "id": 1, "username": "robbie", "text": "hi" }),
+        json!({ "type": "pong", "nonce": 7 }),
+    ];
+    for (message, shape) in messages.into_iter().zip(shapes) {
+        assert_eq!(serde_json::to_value(&message).unwrap(), shape);
+        let parsed: ServerMessage = serde_json::from_value(shape.clone()).unwrap();
+        assert_eq!(serde_json::to_value(parsed).unwrap(), shape);
+    }
+
+    // the old externally tagged shape isn't understood
+    let old = serde_json::from_value::<PlayerMessage>(json!({ "Chat": { "text": "hi" } }));
+    assert!(old.is_err());
+}
+
+#[test]
+fn usernames_in_messages_are_validated() {
+    let message: PlayerMessage =
+        serde_json::from_value(json!({ "type": "whisper", "to": "  robbie ", "text": "psst" }))
+            .unwrap();
+    let PlayerMessage::Whisper { to, .. } = message else {
+        panic!("not a whisper");
+    };
+    assert_eq!(to.as_str(), "robbie");
+
+    for to in ["", "   ", "<b>robbie</b>", "robbie\u{7}"] {
+        let message =
+            serde_json::from_value::<PlayerMessage>(json!({ "type": "transfer_host", "to": to }));
+        assert!(message.is_err(), "{to:?} got through");
+    }
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use common::{connect, expect, post_ok, say, serve};
+use serde_json::{Value, json};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{
+    layer::{Context, Layer, SubscriberExt},
+    registry,
+};
+
+type Events = Arc<Mutex<Vec<BTreeMap<String, String>>>>;
+
+/// keeps the fields of every `game.outcome` event.
+struct Capture(Events);
+
+impl<S: Subscriber> Layer<S> for Capture {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if event.metadata().target() == "game.outcome" {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Fields(BTreeMap<String, String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().into(), format!("{value:?}"));
+    }
+}
+
+#[tokio::test]
+async fn game_over_logs_the_outcome_once() {
+    let events = Events::default();
+    // the test runtime is single threaded, so this sees the server's events too
+    let _guard = tracing::subscriber::set_default(registry().with(Capture(events.clone())));
+
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": 7, "rounds": 2 }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    say(
+        &mut host,
+        json!({ "type": "adjust_points", "username": "host", "delta": 2 }),
+    )
+    .await;
+    say(&mut host, json!({ "type": "start" })).await;
+    for _ in 0..2 {
+        expect(&mut host, "phase_changed").await;
+        say(&mut host, json!({ "type": "pass" })).await;
+    }
+    expect(&mut host, "game_over").await;
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    let outcome = &events[0];
+    assert_eq!(outcome["code"], created["code"].as_str().unwrap());
+    assert_eq!(outcome["seed"], "7");
+    assert_eq!(outcome["rounds"], "2");
+    assert_eq!(outcome["players"], "1");
+    let standings: Value = serde_json::from_str(&outcome["standings"]).unwrap();
+    assert_eq!(standings[0]["username"], "host");
+    assert_eq!(standings[0]["points"], 2);
+}
@@ -0,0 +1,978 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+};
+use common::{
+    app, connect, expect, get, open, post_json, post_json_with_token, post_ok, post_with_token,
+    say, send, serve_with,
+};
+use rusty_robots::{config::ServerConfig, init_game_server};
+use serde_json::{Value, json};
+use tokio::time::{sleep, timeout};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn create_join_and_list() {
+    let app = app();
+
+    let (status, created) = post_json(&app, "/rooms/create", json!({ "username": "host" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["username"], "host");
+    assert!(created["token"].is_string());
+    let code = created["code"].as_str().unwrap();
+
+    let (status, joined) = post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(joined["username"], "robbie");
+    assert_ne!(joined["token"], created["token"]);
+
+    let (status, rooms) = get(&app, "/rooms").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        rooms,
+        json!([{
+            "code": code,
+            "host": "host",
+            "player_count": 2,
+            "connected_count": 0,
+            "spectator_count": 0,
+            "started": false,
+        }])
+    );
+
+    let (status, summary) = get(&app, &format!("/rooms/{code}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary, rooms[0]);
+}
+
+#[tokio::test]
+async fn malformed_room_codes_are_refused() {
+    let app = app();
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+
+    let long = "A".repeat(10_000);
+    for (bad, problem) in [
+        ("ABC", "must be exactly 4 characters"),
+        (long.as_str(), "must be exactly 4 characters"),
+        ("AB.D", "must not contain '.'"),
+        ("AB%2FD", "must not contain '/'"),
+    ] {
+        let (status, body) = post_json(
+            &app,
+            &format!("/rooms/{bad}/join"),
+            json!({ "username": "robbie" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{bad}");
+        assert_eq!(body["error"], "invalid_code");
+        assert_eq!(body["message"], format!("room code invalid: {problem}"));
+    }
+
+    // well formed codes are still looked up in whatever case they're typed
+    let (status, summary) = get(&app, &format!("/rooms/{}", code.to_lowercase())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["code"], code);
+}
+
+#[tokio::test]
+async fn lowercase_codes_join_the_uppercase_room() {
+    let (app, addr) = serve_with(ServerConfig::default()).await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    assert_eq!(code, code.to_uppercase());
+    assert!(!code.contains(['0', 'O', '1', 'I']), "{code}");
+
+    let lowercase = json!(code.to_lowercase());
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", lowercase.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let mut robbie = connect(addr, &lowercase, &joined["token"]).await;
+
+    say(&mut robbie, json!({ "type": "chat", "text": "hi" })).await;
+    assert_eq!(expect(&mut host, "chat").await["username"], "robbie");
+}
+
+#[tokio::test]
+async fn join_with_taken_username_conflicts() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    let (status, created) = post_json(&app, "/rooms/create", json!({ "username": "host" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let join_uri = format!("/rooms/{}/join", created["code"].as_str().unwrap());
+
+    let (status, _) = post_json(&app, &join_uri, json!({ "username": "robbie" })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = post_json(&app, &join_uri, json!({ "username": "robbie" })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "player_exists");
+    assert_eq!(body["message"], "player 'robbie' already exists");
+}
+
+#[tokio::test]
+async fn errors_have_the_same_json_shape() {
+    let app = app();
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let missing = "A".repeat(code.len());
+    let missing = if missing == code {
+        "B".repeat(code.len())
+    } else {
+        missing
+    };
+
+    let (status, body) = get(&app, &format!("/rooms/{missing}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        body,
+        json!({ "error": "room_not_found", "message": "room not found" })
+    );
+
+    // a room's own errors come out the same way
+    let (status, body) = post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "host" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(
+        body,
+        json!({ "error": "player_exists", "message": "player 'host' already exists" })
+    );
+
+    // with the problem fields listed when there's more than one
+    let (status, body) = post_json(
+        &app,
+        "/rooms/create",
+        json!({ "username": "", "password": " " }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].is_string());
+    assert!(body["message"].is_string());
+    assert_eq!(
+        body["fields"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        ["password", "username"]
+    );
+}
+
+#[tokio::test]
+async fn tampered_tokens_are_rejected() {
+    let app = app();
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let uri = format!("/rooms/{}/settings", created["code"].as_str().unwrap());
+
+    let (status, _) = post_json_with_token(&app, &uri, &created["token"], json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let token = created["token"].as_str().unwrap();
+    let flipped = if token.starts_with('A') { "B" } else { "A" };
+    for tampered in [format!("{flipped}{}", &token[1..]), token[1..].to_string()] {
+        let (status, body) = post_json_with_token(&app, &uri, &json!(tampered), json!({})).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{tampered}");
+        assert_eq!(body["error"], "invalid_token");
+    }
+}
+
+#[tokio::test]
+async fn start_over_http() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    let (_, created) = post_json(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let start_uri = format!("/rooms/{code}/start");
+    let (_, joined) = post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    let (status, body) = post_with_token(&app, &start_uri, &joined["token"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "not_host");
+
+    let (status, _) = post_with_token(&app, &start_uri, &created["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = post_with_token(&app, &start_uri, &created["token"]).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "game_started");
+}
+
+#[tokio::test]
+async fn room_knows_its_own_code() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    let (_, created) = post_json(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let (status, summary) = send(
+        &app,
+        Request::get(format!("/rooms/{}", created["code"].as_str().unwrap()))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["code"], created["code"]);
+}
+
+#[tokio::test]
+async fn missing_usernames_are_generated() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    let (status, created) = post_json(&app, "/rooms/create", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let host = created["username"].as_str().unwrap();
+    assert!(host.starts_with("Robot-"));
+
+    let (status, joined) = post_json(
+        &app,
+        &format!("/rooms/{}/join", created["code"].as_str().unwrap()),
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let player = joined["username"].as_str().unwrap();
+    assert!(player.starts_with("Robot-"));
+    assert_ne!(host, player);
+}
+
+#[tokio::test]
+async fn rooms_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("rusty-robots-{}.json", std::process::id()));
+    let config = ServerConfig {
+        persist_path: Some(path.clone()),
+        ..ServerConfig::default()
+    };
+
+    let (app, handle) = init_game_server(&config);
+    let (_, created) = post_json(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    handle.persist().await.unwrap();
+
+    let (app, _) = init_game_server(&config);
+    std::fs::remove_file(&path).unwrap();
+
+    let (status, summary) = send(
+        &app,
+        Request::get(format!("/rooms/{code}"))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["player_count"], 2);
+
+    let (status, _) =
+        post_with_token(&app, &format!("/rooms/{code}/start"), &created["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn health_and_metrics() {
+    let (app, handle) = init_game_server(&ServerConfig::default());
+    let monitoring = handle.monitoring();
+
+    let response = monitoring
+        .clone()
+        .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    post_json(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let (status, metrics) = send(
+        &monitoring,
+        Request::get("/metrics").body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(metrics["rooms"], 1);
+    assert_eq!(metrics["connected_players"], 0);
+    assert_eq!(metrics["messages_sent"], 0);
+}
+
+#[tokio::test]
+async fn same_seed_same_board() {
+    let path = std::env::temp_dir().join(format!("rusty-robots-seed-{}.json", std::process::id()));
+    let config = ServerConfig {
+        persist_path: Some(path.clone()),
+        ..ServerConfig::default()
+    };
+
+    let (app, handle) = init_game_server(&config);
+    let mut codes = Vec::new();
+    for seed in [7, 7, 8] {
+        let (_, created) = post_json(&app, "/rooms/create", json!({ "seed": seed })).await;
+        codes.push(created["code"].clone());
+    }
+    handle.persist().await.unwrap();
+
+    let rooms: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let board = |code: &Value| {
+        rooms
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|room| &room["code"] == code)
+            .unwrap()["board"]
+            .clone()
+    };
+
+    assert_eq!(board(&codes[0]), board(&codes[1]));
+    assert_ne!(board(&codes[0]), board(&codes[2]));
+}
+
+#[tokio::test]
+async fn full_rooms_reject_joins_until_someone_leaves() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    let (_, created) = post_json(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "max_players": 2 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let join_uri = format!("/rooms/{code}/join");
+
+    let (status, joined) = post_json(&app, &join_uri, json!({ "username": "robbie" })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = post_json(&app, &join_uri, json!({ "username": "roberta" })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "room_full");
+
+    let (status, _) =
+        post_with_token(&app, &format!("/rooms/{code}/leave"), &joined["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = post_json(&app, &join_uri, json!({ "username": "roberta" })).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn max_players_must_be_in_range() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    for max_players in [1, 17] {
+        let (status, body) =
+            post_json(&app, "/rooms/create", json!({ "max_players": max_players })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "invalid_setting");
+    }
+}
+
+#[tokio::test]
+async fn phase_lengths_must_be_in_range() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    for setting in ["bid_seconds", "solve_seconds"] {
+        for seconds in [0, 4, 301] {
+            let (status, body) =
+                post_json(&app, "/rooms/create", json!({ setting: seconds })).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["error"], "invalid_setting");
+            assert!(body["message"].as_str().unwrap().contains(setting));
+        }
+    }
+}
+
+#[tokio::test]
+async fn hidden_scores_cant_break_ties_by_score() {
+    let app = app();
+
+    let (status, body) = post_json(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "tie_break": "score", "hide_scores_until_end": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_setting");
+    assert!(body["message"].as_str().unwrap().contains("tie_break"));
+
+    for tie_break in ["earliest", "random"] {
+        post_ok(
+            &app,
+            "/rooms/create",
+            json!({ "username": "host", "tie_break": tie_break, "hide_scores_until_end": true }),
+        )
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn boards_come_in_a_few_sizes() {
+    let (app, addr) = serve_with(ServerConfig::default()).await;
+
+    for board_size in [4, 10, 20] {
+        let (status, body) =
+            post_json(&app, "/rooms/create", json!({ "board_size": board_size })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "invalid_setting");
+    }
+
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "board_size": 8 }),
+    )
+    .await;
+    let mut host = open(addr, &created["code"], &created["token"]).await;
+    let welcome = expect(&mut host, "welcome").await;
+    assert_eq!(welcome["board"]["size"], 8);
+}
+
+#[cfg(not(feature = "client"))]
+#[tokio::test]
+async fn cors_allows_configured_origins() {
+    let config = ServerConfig {
+        cors_origins: vec!["http://localhost:5173".parse().unwrap()],
+        ..ServerConfig::default()
+    };
+    let (app, _) = init_game_server(&config);
+
+    for (origin, allowed) in [
+        ("http://localhost:5173", true),
+        ("http://evil.example", false),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/rooms")
+                    .header("Origin", origin)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get("Access-Control-Allow-Origin")
+                .is_some(),
+            allowed
+        );
+    }
+}
+
+#[tokio::test]
+async fn create_sets_the_token_cookie() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/rooms/create")
+                .header("Content-Type", "application/json")
+                .body(Body::from(json!({ "username": "host" }).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let cookie = response.headers()["Set-Cookie"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let created: Value = serde_json::from_slice(&body).unwrap();
+
+    // the cookie alone should be enough to act as the host
+    let pair = cookie.split(';').next().unwrap();
+    let (status, _) = send(
+        &app,
+        Request::post(format!(
+            "/rooms/{}/start",
+            created["code"].as_str().unwrap()
+        ))
+        .header("Cookie", pair)
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    for flag in ["HttpOnly", "SameSite=Strict", "Path=/", "Secure"] {
+        assert!(cookie.contains(flag), "{cookie} is missing {flag}");
+    }
+}
+
+#[tokio::test]
+async fn responses_carry_the_trimmed_username() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    let (_, created) = post_json(&app, "/rooms/create", json!({ "username": "  host " })).await;
+    assert_eq!(created["username"], "host");
+
+    let (_, joined) = post_json(
+        &app,
+        &format!("/rooms/{}/join", created["code"].as_str().unwrap()),
+        json!({ "username": "\trobbie  " }),
+    )
+    .await;
+    assert_eq!(joined["username"], "robbie");
+}
+
+#[tokio::test]
+async fn markup_in_usernames_is_rejected() {
+    let (app, _) = init_game_server(&ServerConfig::default());
+
+    let (status, body) = post_json(
+        &app,
+        "/rooms/create",
+        json!({ "username": "<b>robbie</b>" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_username");
+    assert_eq!(body["message"], "username invalid: must not contain '<'");
+}
+
+#[tokio::test]
+async fn disconnected_players_can_rejoin_the_lobby() {
+    let app = app();
+    let (_, created) = post_json(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let (_, joined) = post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    // in any case, and with the name back as it was joined with
+    let (status, rejoined) = post_json(
+        &app,
+        &format!("/rooms/{code}/rejoin"),
+        json!({ "username": "ROBBIE" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(rejoined["username"], "robbie");
+
+    // the lost token stops working once a new one is issued
+    let leave = format!("/rooms/{code}/leave");
+    let (status, _) = post_with_token(&app, &leave, &joined["token"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = post_with_token(&app, &leave, &rejoined["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn create_with_key(app: &Router, key: &str) -> Value {
+    let client = SocketAddr::from(([127, 0, 0, 1], 4000));
+    let (status, body) = create_from(app, client, key, json!({ "username": "host" })).await;
+    assert_eq!(status, StatusCode::OK);
+    body
+}
+
+/// creates a room from `body` as though `client` had sent it, with `key` to retry it by.
+async fn create_from(
+    app: &Router,
+    client: SocketAddr,
+    key: &str,
+    body: Value,
+) -> (StatusCode, Value) {
+    send(
+        app,
+        Request::post("/rooms/create")
+            .header("Content-Type", "application/json")
+            .header("Idempotency-Key", key)
+            .extension(ConnectInfo(client))
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn retried_creates_return_the_same_room() {
+    let app = app();
+
+    let first = create_with_key(&app, "abc123").await;
+    let retried = create_with_key(&app, "abc123").await;
+    assert_eq!(retried, first);
+
+    let (_, rooms) = get(&app, "/rooms").await;
+    assert_eq!(rooms.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn different_idempotency_keys_create_different_rooms() {
+    let app = app();
+
+    let first = create_with_key(&app, "abc123").await;
+    let second = create_with_key(&app, "def456").await;
+    assert_ne!(second["code"], first["code"]);
+
+    let (_, rooms) = get(&app, "/rooms").await;
+    assert_eq!(rooms.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn idempotency_keys_cant_be_reused_for_another_request() {
+    let app = app();
+
+    create_with_key(&app, "abc123").await;
+    let client = SocketAddr::from(([127, 0, 0, 1], 4000));
+    let (status, body) =
+        create_from(&app, client, "abc123", json!({ "username": "roberta" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"], "idempotency_key_reused");
+}
+
+#[tokio::test]
+async fn idempotency_keys_are_kept_apart_per_client() {
+    let app = app();
+    let body = json!({ "username": "host" });
+
+    let mine = SocketAddr::from(([127, 0, 0, 1], 4000));
+    let (_, first) = create_from(&app, mine, "abc123", body.clone()).await;
+    let theirs = SocketAddr::from(([127, 0, 0, 2], 4000));
+    let (status, second) = create_from(&app, theirs, "abc123", body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(second["code"], first["code"]);
+    assert_ne!(second["token"], first["token"]);
+}
+
+#[tokio::test]
+async fn names_that_only_differ_in_encoding_or_case_conflict() {
+    let app = app();
+    // "café" spelled with a combining accent, which should be stored precomposed
+    let (status, created) =
+        post_json(&app, "/rooms/create", json!({ "username": "cafe\u{301}" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["username"], "caf\u{e9}");
+    let code = created["code"].as_str().unwrap();
+
+    for username in ["caf\u{e9}", "cafe\u{301}", "CAF\u{c9}"] {
+        let (status, body) = post_json(
+            &app,
+            &format!("/rooms/{code}/join"),
+            json!({ "username": username }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT, "{username} joined");
+        assert_eq!(body["error"], "player_exists");
+    }
+}
+
+#[tokio::test]
+async fn tokens_never_show_up_in_debug_output() {
+    let (app, handle) = init_game_server(&ServerConfig {
+        admin_token: Some("admin-secret".into()),
+        ..ServerConfig::default()
+    });
+    // kept around for retries, so the server holds on to the token itself
+    let created = create_with_key(&app, "abc123").await;
+    let token = created["token"].as_str().unwrap();
+
+    let debug = format!("{handle:?}");
+    assert!(debug.contains("Token(redacted)"));
+    assert!(!debug.contains(token));
+    assert!(!debug.contains("admin-secret"));
+}
+
+#[tokio::test]
+async fn every_invalid_field_is_reported_at_once() {
+    let app = app();
+
+    let (status, body) = post_json(
+        &app,
+        "/rooms/create",
+        json!({ "username": "   ", "password": "", "max_players": 1 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    // the first problem, for clients that only look at one
+    assert_eq!(body["error"], "invalid_username");
+    assert_eq!(
+        body["fields"],
+        json!({
+            "username": "username invalid: must not be only whitespace",
+            "password": "password invalid: must not be empty",
+            "max_players": "setting 'max_players' is out of range",
+        })
+    );
+
+    let (_, rooms) = get(&app, "/rooms").await;
+    assert_eq!(rooms, json!([]));
+}
+
+#[tokio::test]
+async fn joins_are_checked_like_creates() {
+    let app = app();
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+
+    let (status, body) = post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "<robbie>", "password": " " }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_username");
+    assert_eq!(
+        body["fields"],
+        json!({
+            "username": "username invalid: must not contain '<'",
+            "password": "password invalid: must not be only whitespace",
+        })
+    );
+
+    // what was asked for comes back as it's stored
+    let (status, joined) = post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "  robbie " }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(joined["username"], "robbie");
+
+    let (status, body) = post_json(&app, &format!("/rooms/{code}/rejoin"), json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_username");
+    assert_eq!(
+        body["fields"]["username"],
+        "username invalid: must not be empty"
+    );
+}
+
+#[tokio::test]
+async fn locked_rooms_only_let_in_the_right_password() {
+    let app = app();
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "password": "hunter2" }),
+    )
+    .await;
+    let uri = format!("/rooms/{}/join", created["code"].as_str().unwrap());
+
+    for body in [
+        json!({ "username": "robbie" }),
+        json!({ "username": "robbie", "password": "hunter3" }),
+        json!({ "username": "robbie", "password": "HUNTER2" }),
+    ] {
+        let (status, refused) = post_json(&app, &uri, body).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(refused["error"], "incorrect_password");
+    }
+
+    let (status, joined) = post_json(
+        &app,
+        &uri,
+        json!({ "username": "robbie", "password": "hunter2" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(joined["username"], "robbie");
+}
+
+#[tokio::test]
+async fn passwords_must_be_short_and_not_blank() {
+    let app = app();
+
+    for (password, problem) in [
+        (String::new(), "must not be empty"),
+        (" \t ".to_string(), "must not be only whitespace"),
+        ("x".repeat(129), "must be at most 128 characters"),
+    ] {
+        let (status, body) = post_json(
+            &app,
+            "/rooms/create",
+            json!({ "username": "host", "password": password }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "invalid_password");
+        assert_eq!(body["message"], format!("password invalid: {problem}"));
+    }
+
+    // spaces are fine inside a password, and so is the longest one allowed
+    for password in [" hunter 2 ".to_string(), "\u{e9}".repeat(128)] {
+        let (status, _) = post_json(
+            &app,
+            "/rooms/create",
+            json!({ "username": "host", "password": password }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn rooms_say_whether_they_need_a_password() {
+    let app = app();
+    let open = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let locked = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "password": "hunter2" }),
+    )
+    .await;
+
+    for (room, expected) in [(open, false), (locked, true)] {
+        let (status, body) = get(
+            &app,
+            &format!(
+                "/rooms/{}/requires-password",
+                room["code"].as_str().unwrap()
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "requires_password": expected }));
+    }
+
+    let (status, _) = get(&app, "/rooms/ZZZZ/requires-password").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn creation_is_refused_at_the_room_cap() {
+    let (app, _) = init_game_server(&ServerConfig {
+        max_rooms: 1,
+        results_ttl_seconds: 1,
+        ..ServerConfig::default()
+    });
+    let request = json!({ "username": "host", "rounds": 1, "bid_seconds": 5 });
+    let created = post_ok(&app, "/rooms/create", request.clone()).await;
+
+    let (status, body) = post_json(&app, "/rooms/create", request.clone()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error"], "too_many_rooms");
+
+    // nobody bids, so the only round runs out and the finished room is soon cleared away
+    let code = created["code"].as_str().unwrap();
+    let (status, _) =
+        post_with_token(&app, &format!("/rooms/{code}/start"), &created["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+    timeout(Duration::from_secs(20), async {
+        while post_json(&app, "/rooms/create", request.clone()).await.0 != StatusCode::OK {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("room never reaped");
+}
+
+#[tokio::test]
+async fn a_stalled_room_answers_busy_instead_of_hanging() {
+    let (app, addr) = serve_with(ServerConfig {
+        busy_timeout_millis: 200,
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let uri = format!("/rooms/{code}");
+
+    // the host never reads their socket, so the welcomes they keep asking for back up until the
+    // room is stuck sending to them
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let busy = timeout(Duration::from_secs(10), async {
+        loop {
+            for _ in 0..50 {
+                say(&mut host, json!({ "type": "resync" })).await;
+            }
+            let (status, body) = get(&app, &uri).await;
+            if status != StatusCode::OK {
+                break (status, body);
+            }
+        }
+    })
+    .await
+    .expect("room never stalled");
+    assert_eq!(busy.0, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(busy.1["error"], "room_busy");
+
+    // the rest of the server carries on without it
+    let (status, rooms) = get(&app, "/rooms").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(rooms, json!([]));
+    post_ok(&app, "/rooms/create", json!({ "username": "other" })).await;
+}
+
+#[tokio::test]
+async fn rooms_whose_host_never_connects_are_dropped() {
+    let (app, addr) = serve_with(ServerConfig {
+        host_connect_timeout_seconds: 1,
+        ..ServerConfig::default()
+    })
+    .await;
+    let abandoned = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let kept = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let _host = connect(addr, &kept["code"], &kept["token"]).await;
+
+    let uri = format!("/rooms/{}", abandoned["code"].as_str().unwrap());
+    timeout(Duration::from_secs(10), async {
+        while get(&app, &uri).await.0 != StatusCode::NOT_FOUND {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("abandoned room never dropped");
+
+    let (status, _) = get(&app, &format!("/rooms/{}", kept["code"].as_str().unwrap())).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn private_rooms_are_unlisted_but_joinable() {
+    let app = app();
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "private": true }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    post_ok(&app, "/rooms/create", json!({ "username": "other" })).await;
+
+    let (_, rooms) = get(&app, "/rooms").await;
+    let rooms = rooms.as_array().unwrap();
+    assert_eq!(rooms.len(), 1);
+    assert_eq!(rooms[0]["host"], "other");
+
+    let (status, _) = post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
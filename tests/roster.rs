@@ -0,0 +1,479 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use common::{
+    connect, expect, expect_any, get, open, open_with_query, post_json, post_ok, say, serve,
+    serve_with,
+};
+use rusty_robots::config::ServerConfig;
+use serde_json::{Value, json};
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Error, client::IntoClientRequest},
+};
+
+#[tokio::test]
+async fn connecting_and_awards_update_the_player() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    let mut host = open_with_query(addr, code, &created["token"], "player_updates=true").await;
+    expect(&mut host, "welcome").await;
+    let update = expect(&mut host, "player_update").await;
+    assert_eq!(update["descriptor"]["username"], "host");
+    assert_eq!(update["connected"], true);
+
+    let _robbie = connect(addr, code, &joined["token"]).await;
+    let update = expect(&mut host, "player_update").await;
+    assert_eq!(update["descriptor"]["username"], "robbie");
+    assert_eq!(update["connected"], true);
+
+    say(
+        &mut host,
+        json!({ "type": "adjust_points", "username": "robbie", "delta": 2 }),
+    )
+    .await;
+    let update = expect(&mut host, "player_update").await;
+    assert_eq!(update["descriptor"]["username"], "robbie");
+    assert_eq!(update["descriptor"]["points"], 2);
+}
+
+#[tokio::test]
+async fn player_updates_are_opt_in() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    say(
+        &mut host,
+        json!({ "type": "adjust_points", "username": "host", "delta": 1 }),
+    )
+    .await;
+    say(&mut host, json!({ "type": "ping", "nonce": 1 })).await;
+    // anything sent before the pong would have been the update
+    let next = expect_any(&mut host, &["player_update", "pong"]).await;
+    assert_eq!(next["type"], "pong");
+}
+
+#[tokio::test]
+async fn welcome_shows_who_is_connected() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    let mut host = open(addr, code, &created["token"]).await;
+    let welcome = expect(&mut host, "welcome").await;
+    let players = welcome["players"].as_array().unwrap();
+    let connected = |username: &str| {
+        players
+            .iter()
+            .find(|player| player["username"] == username)
+            .unwrap()["connected"]
+            .clone()
+    };
+    assert_eq!(connected("host"), true);
+    assert_eq!(connected("robbie"), false);
+}
+
+#[tokio::test]
+async fn welcome_carries_the_room_code_and_round_settings() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "rounds": 3, "bid_seconds": 45 }),
+    )
+    .await;
+
+    // and again on a resync, so a client that lost track can rebuild everything from it
+    let mut host = open(addr, &created["code"], &created["token"]).await;
+    for resync in [false, true] {
+        if resync {
+            say(&mut host, json!({ "type": "resync" })).await;
+        }
+        let welcome = expect(&mut host, "welcome").await;
+        assert_eq!(welcome["code"], created["code"]);
+        assert_eq!(welcome["username"], "host");
+        assert_eq!(welcome["host"], "host");
+        assert_eq!(welcome["rounds"], 3);
+        assert_eq!(welcome["bid_seconds"], 45);
+        assert_eq!(welcome["target_score"], Value::Null);
+        assert_eq!(welcome["phase"], Value::Null);
+        assert_eq!(welcome["seconds_left"], Value::Null);
+    }
+}
+
+#[tokio::test]
+async fn connected_players_cannot_be_rejoined() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let _host = connect(addr, code, &created["token"]).await;
+
+    let (status, body) = post_json(
+        &app,
+        &format!("/rooms/{}/rejoin", code.as_str().unwrap()),
+        json!({ "username": "host" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "player_connected");
+}
+
+#[tokio::test]
+async fn dropped_connections_are_cleaned_up_once() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, code, &created["token"]).await;
+    let robbie = connect(addr, code, &joined["token"]).await;
+
+    // messages keep coming while the socket goes away, so some may find nobody listening
+    drop(robbie);
+    for _ in 0..3 {
+        say(
+            &mut host,
+            json!({ "type": "whisper", "to": "robbie", "text": "still there?" }),
+        )
+        .await;
+    }
+    assert_eq!(expect(&mut host, "disconnect").await["username"], "robbie");
+
+    say(&mut host, json!({ "type": "ping", "nonce": 1 })).await;
+    let next = expect_any(&mut host, &["disconnect", "pong"]).await;
+    assert_eq!(next["type"], "pong");
+
+    say(
+        &mut host,
+        json!({ "type": "whisper", "to": "robbie", "text": "hello?" }),
+    )
+    .await;
+    assert_eq!(
+        expect(&mut host, "error").await["code"],
+        "player_disconnected"
+    );
+
+    let _robbie = connect(addr, code, &joined["token"]).await;
+    assert_eq!(expect(&mut host, "connect").await["username"], "robbie");
+}
+
+#[tokio::test]
+async fn broadcasts_clean_up_dropped_connections() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let mut tokens = Vec::new();
+    for username in ["robbie", "roberta"] {
+        let joined = post_ok(
+            &app,
+            &format!("/rooms/{}/join", code.as_str().unwrap()),
+            json!({ "username": username }),
+        )
+        .await;
+        tokens.push(joined["token"].clone());
+    }
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &tokens[0]).await;
+    let roberta = connect(addr, code, &tokens[1]).await;
+
+    drop(roberta);
+    say(&mut host, json!({ "type": "chat", "text": "anyone?" })).await;
+    // roberta may be noticed gone before or after the chat goes out
+    let mut seen = Vec::new();
+    for _ in 0..2 {
+        let message = expect_any(&mut robbie, &["disconnect", "chat"]).await;
+        if message["type"] == "disconnect" {
+            assert_eq!(message["username"], "roberta");
+        }
+        seen.push(message["type"].as_str().unwrap().to_owned());
+    }
+    seen.sort();
+    assert_eq!(seen, ["chat", "disconnect"]);
+
+    say(&mut host, json!({ "type": "chat", "text": "just us" })).await;
+    let next = expect_any(&mut robbie, &["disconnect", "chat"]).await;
+    assert_eq!(next["text"], "just us");
+}
+
+#[tokio::test]
+async fn one_address_can_only_hold_so_many_connections() {
+    let (app, addr) = serve_with(ServerConfig {
+        max_connections_per_ip: 2,
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let robbie = || {
+        let mut request = format!("ws://{addr}/rooms/{code}/ws")
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Authorization",
+            format!("Bearer {}", joined["token"].as_str().unwrap())
+                .parse()
+                .unwrap(),
+        );
+        connect_async(request)
+    };
+
+    let _host = connect(addr, &created["code"], &created["token"]).await;
+    let (spectator, _) = connect_async(format!("ws://{addr}/rooms/{code}/spectate"))
+        .await
+        .unwrap();
+
+    let Err(Error::Http(response)) = robbie().await else {
+        panic!("third connection allowed");
+    };
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // closing one frees a slot up, once the server notices
+    drop(spectator);
+    timeout(Duration::from_secs(5), async {
+        while robbie().await.is_err() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("slot never freed");
+}
+
+#[tokio::test]
+async fn players_pick_distinct_colors() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, code, &created["token"]).await;
+
+    say(&mut host, json!({ "type": "set_color", "color": "teal" })).await;
+    let changed = expect(&mut host, "color_changed").await;
+    assert_eq!(changed["username"], "host");
+    assert_eq!(changed["color"], "teal");
+
+    let mut robbie = open(addr, code, &joined["token"]).await;
+    let welcome = expect(&mut robbie, "welcome").await;
+    let host_row = welcome["players"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|player| player["username"] == "host")
+        .unwrap();
+    assert_eq!(host_row["color"], "teal");
+
+    say(&mut robbie, json!({ "type": "set_color", "color": "teal" })).await;
+    assert_eq!(expect(&mut robbie, "error").await["code"], "color_taken");
+    say(&mut robbie, json!({ "type": "set_color", "color": "pink" })).await;
+    assert_eq!(expect(&mut host, "color_changed").await["color"], "pink");
+}
+
+/// `username`'s roster row in a welcome.
+fn row<'a>(welcome: &'a Value, username: &str) -> &'a Value {
+    welcome["players"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|player| player["username"] == username)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn players_show_how_long_they_have_been_away() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    let mut host = open_with_query(addr, code, &created["token"], "player_updates=true").await;
+    let welcome = expect(&mut host, "welcome").await;
+    // robbie hasn't been seen at all yet
+    assert_eq!(row(&welcome, "robbie")["away_seconds"], Value::Null);
+    expect(&mut host, "player_update").await;
+
+    let robbie = connect(addr, code, &joined["token"]).await;
+    let update = expect(&mut host, "player_update").await;
+    assert_eq!(update["descriptor"]["username"], "robbie");
+    assert_eq!(update["descriptor"]["away_seconds"], Value::Null);
+
+    drop(robbie);
+    let update = expect(&mut host, "player_update").await;
+    assert_eq!(update["connected"], false);
+    assert_eq!(update["descriptor"]["away_seconds"], 0);
+
+    sleep(Duration::from_millis(1100)).await;
+    say(&mut host, json!({ "type": "resync" })).await;
+    let welcome = expect(&mut host, "welcome").await;
+    assert_eq!(row(&welcome, "robbie")["away_seconds"], 1);
+
+    let _robbie = connect(addr, code, &joined["token"]).await;
+    let update = expect(&mut host, "player_update").await;
+    assert_eq!(update["connected"], true);
+    assert_eq!(update["descriptor"]["away_seconds"], Value::Null);
+}
+
+#[tokio::test]
+async fn spectators_are_counted_and_named() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let uri = format!("/rooms/{code}/spectators");
+
+    let (status, body) = get(&app, &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "count": 0, "names": [] }));
+
+    let mut watchers = Vec::new();
+    for query in ["name=zed", "", "name=ann"] {
+        let (mut spectator, _) =
+            connect_async(format!("ws://{addr}/rooms/{code}/spectate?{query}"))
+                .await
+                .unwrap();
+        expect(&mut spectator, "hello").await;
+        watchers.push(spectator);
+    }
+    let (_, body) = get(&app, &uri).await;
+    assert_eq!(body, json!({ "count": 3, "names": ["ann", "zed"] }));
+
+    // leaving takes them off the list, once the server notices
+    watchers.remove(0);
+    timeout(Duration::from_secs(5), async {
+        while get(&app, &uri).await.1 != json!({ "count": 2, "names": ["ann"] }) {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("spectator never left");
+
+    let (status, _) = get(&app, "/rooms/ZZZZ/spectators").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn spectators_see_chat_but_cant_bid() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let (mut spectator, _) = connect_async(format!("ws://{addr}/rooms/{code}/spectate"))
+        .await
+        .unwrap();
+    expect(&mut spectator, "hello").await;
+
+    say(&mut host, json!({ "type": "chat", "text": "hi all" })).await;
+    let chat = expect(&mut spectator, "chat").await;
+    assert_eq!(chat["username"], "host");
+    assert_eq!(chat["text"], "hi all");
+
+    say(&mut host, json!({ "type": "start" })).await;
+    expect(&mut spectator, "phase_changed").await;
+    say(&mut spectator, json!({ "type": "bid", "moves": 1 })).await;
+    sleep(Duration::from_millis(100)).await;
+
+    // the spectator's bid never reached the room, so the host bids alone
+    say(&mut host, json!({ "type": "bid", "moves": 9 })).await;
+    let bid = expect(&mut host, "bid_placed").await;
+    assert_eq!(bid["username"], "host");
+    let solving = expect(&mut host, "phase_changed").await;
+    assert_eq!(solving["phase"]["solver"], "host");
+    assert_eq!(solving["phase"]["queue"], json!([]));
+}
+
+#[tokio::test]
+async fn spectating_a_locked_room_takes_the_password_header() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "password": "hunter2" }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let spectate = |password: Option<&str>| {
+        let mut request = format!("ws://{addr}/rooms/{code}/spectate")
+            .into_client_request()
+            .unwrap();
+        if let Some(password) = password {
+            request
+                .headers_mut()
+                .insert("Room-Password", password.parse().unwrap());
+        }
+        connect_async(request)
+    };
+
+    for password in [None, Some("hunter3")] {
+        let Err(Error::Http(response)) = spectate(password).await else {
+            panic!("spectating without the password allowed");
+        };
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    let (mut spectator, _) = spectate(Some("hunter2")).await.unwrap();
+    expect(&mut spectator, "hello").await;
+}
+
+#[tokio::test]
+async fn mismatched_protocol_versions_are_refused_before_upgrading() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let mut play = format!("ws://{addr}/rooms/{code}/ws?v=999")
+        .into_client_request()
+        .unwrap();
+    play.headers_mut().insert(
+        "Authorization",
+        format!("Bearer {}", created["token"].as_str().unwrap())
+            .parse()
+            .unwrap(),
+    );
+
+    for request in [
+        play,
+        format!("ws://{addr}/rooms/{code}/spectate?v=999")
+            .into_client_request()
+            .unwrap(),
+    ] {
+        let Err(Error::Http(response)) = connect_async(request).await else {
+            panic!("mismatched protocol version upgraded");
+        };
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    let mut host = open_with_query(addr, &created["code"], &created["token"], "v=4").await;
+    expect(&mut host, "welcome").await;
+}
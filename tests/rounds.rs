@@ -0,0 +1,1395 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{Router, http::StatusCode};
+use common::{
+    Socket, collect_until, connect, expect, expect_any, expect_close, get, open, open_with_query,
+    post_json, post_json_with_token, post_ok, post_with_token, say, serve, serve_with,
+};
+use rusty_robots::{
+    config::ServerConfig,
+    game::{Board, Robot, Target, TargetKind},
+};
+use serde_json::{Value, json};
+use tokio_tungstenite::{connect_async, tungstenite::client::IntoClientRequest};
+
+/// a room with a connected host and a connected, not yet ready, player named robbie.
+async fn lobby(app: &Router, addr: SocketAddr) -> (Socket, Socket) {
+    lobby_with(app, addr, json!({ "username": "host" })).await
+}
+
+/// like `lobby`, but creating the room with `request`.
+async fn lobby_with(app: &Router, addr: SocketAddr, request: Value) -> (Socket, Socket) {
+    let created = post_ok(app, "/rooms/create", request).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    let host = connect(addr, code, &created["token"]).await;
+    let robbie = connect(addr, code, &joined["token"]).await;
+    (host, robbie)
+}
+
+#[tokio::test]
+async fn players_are_welcomed_over_websocket() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = open(addr, code, &joined["token"]).await;
+    let welcome = expect(&mut robbie, "welcome").await;
+    assert_eq!(welcome["username"], "robbie");
+    assert_eq!(welcome["host"], "host");
+    assert_eq!(welcome["players"].as_array().unwrap().len(), 2);
+
+    // the host hears about their own connection first
+    assert_eq!(expect(&mut host, "connect").await["username"], "host");
+    assert_eq!(expect(&mut host, "connect").await["username"], "robbie");
+
+    say(&mut robbie, json!({ "type": "chat", "text": "hi" })).await;
+    let chat = expect(&mut host, "chat").await;
+    assert_eq!(chat["username"], "robbie");
+    assert_eq!(chat["text"], "hi");
+}
+
+#[tokio::test]
+async fn start_waits_for_everyone_to_be_ready() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start" })).await;
+    let error = expect(&mut host, "error").await;
+    assert_eq!(error["code"], "players_not_ready");
+
+    say(&mut robbie, json!({ "type": "set_ready", "ready": true })).await;
+    let ready = expect(&mut host, "ready_changed").await;
+    assert_eq!(ready["username"], "robbie");
+    assert_eq!(ready["ready"], true);
+
+    say(&mut host, json!({ "type": "start" })).await;
+    let started = expect(&mut robbie, "phase_changed").await;
+    assert_eq!(started["phase"]["name"], "bidding");
+}
+
+#[tokio::test]
+async fn only_the_host_starts_and_nobody_else_hears_of_a_refusal() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut robbie, json!({ "type": "start", "force": true })).await;
+    assert_eq!(expect(&mut robbie, "error").await["code"], "not_host");
+    say(&mut robbie, json!({ "type": "ping", "nonce": 1 })).await;
+    let seen = collect_until(&mut robbie, "pong").await;
+    assert!(
+        !seen
+            .iter()
+            .any(|message| message["type"] == "phase_changed")
+    );
+
+    say(&mut host, json!({ "type": "ping", "nonce": 2 })).await;
+    let seen = collect_until(&mut host, "pong").await;
+    assert!(
+        !seen
+            .iter()
+            .any(|message| ["error", "phase_changed"].contains(&message["type"].as_str().unwrap())),
+        "{seen:?}"
+    );
+}
+
+#[tokio::test]
+async fn forced_start_skips_readiness() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    let started = expect(&mut robbie, "phase_changed").await;
+    assert_eq!(started["phase"]["name"], "bidding");
+}
+
+#[tokio::test]
+async fn bidding_ends_early_once_everyone_bids() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "bid_seconds": 60 }),
+    )
+    .await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &joined["token"]).await;
+
+    say(&mut robbie, json!({ "type": "set_ready", "ready": true })).await;
+    expect(&mut host, "ready_changed").await;
+    say(&mut host, json!({ "type": "start" })).await;
+    let started = expect(&mut robbie, "phase_changed").await;
+    assert_eq!(started["phase"]["name"], "bidding");
+    assert_eq!(started["phase"]["round"], 1);
+
+    say(&mut host, json!({ "type": "bid", "moves": 5 })).await;
+    say(&mut robbie, json!({ "type": "bid", "moves": 3 })).await;
+
+    let solving = expect(&mut robbie, "phase_changed").await;
+    assert_eq!(solving["phase"]["name"], "solving");
+    assert_eq!(solving["phase"]["solver"], "robbie");
+    assert_eq!(solving["phase"]["bid"], 3);
+}
+
+#[tokio::test]
+async fn bids_out_of_bounds_are_rejected() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    say(&mut host, json!({ "type": "start" })).await;
+
+    say(&mut host, json!({ "type": "bid", "moves": 0 })).await;
+    let rejected = expect(&mut host, "bid_rejected").await;
+    assert_eq!(rejected["reason"], "too_low");
+
+    say(&mut host, json!({ "type": "bid", "moves": u32::MAX })).await;
+    let rejected = expect(&mut host, "bid_rejected").await;
+    assert_eq!(rejected["reason"], "too_high");
+
+    // neither counted, so a real bid still goes through
+    say(&mut host, json!({ "type": "bid", "moves": 5 })).await;
+    assert_eq!(expect(&mut host, "bid_placed").await["moves"], 5);
+}
+
+#[tokio::test]
+async fn bids_can_be_lowered_but_not_raised() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 10 })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 8 })).await;
+    expect(&mut robbie, "bid_placed").await;
+    let lowered = expect(&mut robbie, "bid_placed").await;
+    assert_eq!(lowered["username"], "host");
+    assert_eq!(lowered["moves"], 8);
+
+    say(&mut host, json!({ "type": "bid", "moves": 9 })).await;
+    let error = expect(&mut host, "error").await;
+    assert_eq!(error["code"], "bid_not_lower");
+
+    say(&mut robbie, json!({ "type": "bid", "moves": 9 })).await;
+    let solving = expect_phase(&mut robbie, "solving").await;
+    assert_eq!(solving["phase"]["solver"], "host");
+    assert_eq!(solving["phase"]["bid"], 8);
+}
+
+#[tokio::test]
+async fn equal_bids_go_to_whoever_bid_first() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    expect_phase(&mut robbie, "bidding").await;
+    say(&mut robbie, json!({ "type": "bid", "moves": 5 })).await;
+    expect(&mut host, "bid_placed").await;
+    say(&mut host, json!({ "type": "bid", "moves": 5 })).await;
+
+    let solving = expect_phase(&mut host, "solving").await;
+    assert_eq!(solving["phase"]["solver"], "robbie");
+    assert_eq!(solving["phase"]["queue"][0]["username"], "host");
+}
+
+#[tokio::test]
+async fn equal_bids_can_go_to_the_leader() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby_with(
+        &app,
+        addr,
+        json!({ "username": "host", "tie_break": "score" }),
+    )
+    .await;
+
+    say(
+        &mut host,
+        json!({ "type": "adjust_points", "username": "robbie", "delta": 1 }),
+    )
+    .await;
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 5 })).await;
+    expect(&mut robbie, "bid_placed").await;
+    say(&mut robbie, json!({ "type": "bid", "moves": 5 })).await;
+
+    let solving = expect_phase(&mut host, "solving").await;
+    assert_eq!(solving["phase"]["solver"], "robbie");
+}
+
+#[tokio::test]
+async fn bidding_without_bids_skips_the_target() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "bid_seconds": 5, "rounds": 2 }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    say(&mut host, json!({ "type": "start" })).await;
+    let remaining = expect(&mut host, "time_remaining").await;
+    assert!(remaining["seconds"].as_u64().unwrap() <= 5);
+
+    let over = expect(&mut host, "round_over").await;
+    assert_eq!(over["round"], 1);
+    assert_eq!(over["winner"], Value::Null);
+
+    let next = expect(&mut host, "phase_changed").await;
+    assert_eq!(next["phase"]["name"], "bidding");
+    assert_eq!(next["phase"]["round"], 2);
+}
+
+#[tokio::test]
+async fn each_target_comes_up_once() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": 7, "rounds": 100 }),
+    )
+    .await;
+    let mut host = open(addr, &created["code"], &created["token"]).await;
+    let welcome = expect(&mut host, "welcome").await;
+    let targets = welcome["board"]["targets"].as_array().unwrap();
+
+    say(&mut host, json!({ "type": "start" })).await;
+    let mut drawn = Vec::new();
+    loop {
+        let changed = expect(&mut host, "phase_changed").await;
+        match changed["phase"]["name"].as_str().unwrap() {
+            "bidding" => {
+                drawn.push(changed["phase"]["target"].clone());
+                say(&mut host, json!({ "type": "pass" })).await;
+            }
+            "game_over" => break,
+            _ => {}
+        }
+    }
+
+    // the pile ran out long before the rounds did
+    assert_eq!(drawn.len(), targets.len());
+    for (i, target) in drawn.iter().enumerate() {
+        assert!(!drawn[..i].contains(target), "{target} drawn twice");
+        assert!(targets.contains(target));
+    }
+}
+
+#[tokio::test]
+async fn results_are_served_once_the_game_is_over() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "rounds": 1 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    let (status, body) = get(&app, &format!("/rooms/{code}/results")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "game_not_over");
+
+    say(&mut host, json!({ "type": "start" })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+    expect_phase(&mut host, "solving").await;
+    let (status, _) = get(&app, &format!("/rooms/{code}/results")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    say(
+        &mut host,
+        json!({ "type": "adjust_points", "username": "host", "delta": 3 }),
+    )
+    .await;
+    say(&mut host, json!({ "type": "pass" })).await;
+    expect(&mut host, "game_over").await;
+
+    let (status, results) = get(&app, &format!("/rooms/{code}/results")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(results["winner"], "host");
+    assert_eq!(results["standings"][0]["username"], "host");
+    assert_eq!(results["standings"][0]["points"], 3);
+}
+
+#[tokio::test]
+async fn reaching_the_target_score_ends_the_game_mid_round() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "target_score": 3 }),
+    )
+    .await;
+    let mut host = open(addr, &created["code"], &created["token"]).await;
+    assert_eq!(expect(&mut host, "welcome").await["target_score"], 3);
+
+    say(&mut host, json!({ "type": "start" })).await;
+    expect_phase(&mut host, "bidding").await;
+    let award = json!({ "type": "adjust_points", "username": "host", "delta": 2 });
+    say(&mut host, award.clone()).await;
+    expect(&mut host, "leaderboard").await;
+    say(&mut host, award).await;
+
+    let over = expect_any(&mut host, &["round_over", "game_over"]).await;
+    assert_eq!(over["type"], "game_over");
+    assert_eq!(over["standings"][0]["points"], 4);
+}
+
+#[tokio::test]
+async fn solving_times_out() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "solve_seconds": 5, "rounds": 1 }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    say(&mut host, json!({ "type": "start" })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+
+    let over = expect(&mut host, "round_over").await;
+    assert_eq!(over["winner"], Value::Null);
+    let over = expect(&mut host, "game_over").await;
+    assert_eq!(over["standings"][0]["points"], 0);
+}
+
+#[tokio::test]
+async fn token_can_be_passed_as_a_subprotocol() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+
+    let mut request = format!("ws://{addr}/rooms/{}/ws", created["code"].as_str().unwrap())
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        "Sec-WebSocket-Protocol",
+        format!("bearer, {}", created["token"].as_str().unwrap())
+            .parse()
+            .unwrap(),
+    );
+
+    let (mut socket, response) = connect_async(request).await.unwrap();
+    assert_eq!(response.headers()["Sec-WebSocket-Protocol"], "bearer");
+
+    let welcome = expect(&mut socket, "welcome").await;
+    assert_eq!(welcome["username"], "host");
+}
+
+#[tokio::test]
+async fn second_connection_is_turned_away() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let mut first = connect(addr, &created["code"], &created["token"]).await;
+
+    let mut second = open(addr, &created["code"], &created["token"]).await;
+    assert_eq!(expect_close(&mut second).await, 4001);
+
+    say(&mut first, json!({ "type": "ping", "nonce": 1 })).await;
+    expect(&mut first, "pong").await;
+}
+
+#[tokio::test]
+async fn second_connection_can_evict_the_first() {
+    let (app, addr) = serve_with(ServerConfig {
+        evict_connections: true,
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let mut first = connect(addr, &created["code"], &created["token"]).await;
+
+    let mut second = connect(addr, &created["code"], &created["token"]).await;
+    assert_eq!(expect_close(&mut first).await, 4002);
+
+    say(&mut second, json!({ "type": "ping", "nonce": 1 })).await;
+    expect(&mut second, "pong").await;
+}
+
+#[tokio::test]
+async fn pongs_only_go_to_whoever_pinged() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut robbie, json!({ "type": "ping", "nonce": 42 })).await;
+    assert_eq!(expect(&mut robbie, "pong").await["nonce"], 42);
+
+    // robbie's pong went out first, so the host would have it queued ahead of their own
+    say(&mut host, json!({ "type": "ping", "nonce": 7 })).await;
+    assert_eq!(expect(&mut host, "pong").await["nonce"], 7);
+}
+
+/// a room where the host alone has bid, and is now solving with plenty of moves to spare.
+async fn solving(app: &Router, addr: SocketAddr) -> Socket {
+    let created = post_ok(
+        app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": 7 }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    say(&mut host, json!({ "type": "start" })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+    expect_phase(&mut host, "solving").await;
+    host
+}
+
+/// skips messages until the room moves into the phase called `name`.
+async fn expect_phase(socket: &mut Socket, name: &str) -> Value {
+    loop {
+        let changed = expect(socket, "phase_changed").await;
+        if changed["phase"]["name"] == name {
+            return changed;
+        }
+    }
+}
+
+/// moves the first robot that can move at all, returning the `robot_moved` message.
+async fn make_a_move(socket: &mut Socket) -> Value {
+    for direction in ["up", "down", "left", "right"] {
+        say(
+            socket,
+            json!({ "type": "move", "robot": "red", "direction": direction }),
+        )
+        .await;
+        let reply = expect_any(socket, &["robot_moved", "move_rejected"]).await;
+        if reply["type"] == "robot_moved" {
+            return reply;
+        }
+    }
+    panic!("red can't move anywhere");
+}
+
+#[tokio::test]
+async fn moves_out_of_turn_are_rejected() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(
+        &mut robbie,
+        json!({ "type": "move", "robot": "red", "direction": "up" }),
+    )
+    .await;
+    let rejected = expect(&mut robbie, "move_rejected").await;
+    assert_eq!(rejected["reason"], "not_your_turn");
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 30 })).await;
+    say(&mut robbie, json!({ "type": "pass" })).await;
+    expect_phase(&mut robbie, "solving").await;
+    say(
+        &mut robbie,
+        json!({ "type": "move", "robot": "red", "direction": "up" }),
+    )
+    .await;
+    let rejected = expect(&mut robbie, "move_rejected").await;
+    assert_eq!(rejected["reason"], "not_your_turn");
+}
+
+#[tokio::test]
+async fn moves_of_unknown_robots_are_rejected() {
+    let (app, addr) = serve().await;
+    let mut host = solving(&app, addr).await;
+
+    say(
+        &mut host,
+        json!({ "type": "move", "robot": "purple", "direction": "up" }),
+    )
+    .await;
+    let rejected = expect(&mut host, "move_rejected").await;
+    assert_eq!(rejected["reason"], "no_such_robot");
+}
+
+#[tokio::test]
+async fn moves_that_go_nowhere_are_rejected() {
+    let (app, addr) = serve().await;
+    let mut host = solving(&app, addr).await;
+
+    let up = json!({ "type": "move", "robot": "red", "direction": "up" });
+    say(&mut host, up.clone()).await;
+    // either red is already against something, or it slides as far as it can and then is
+    let reply = expect_any(&mut host, &["robot_moved", "move_rejected"]).await;
+    if reply["type"] == "robot_moved" {
+        say(&mut host, up).await;
+    }
+    let rejected = expect(&mut host, "move_rejected").await;
+    assert_eq!(rejected["reason"], "cannot_move");
+}
+
+#[tokio::test]
+async fn spectators_watch_moves_as_they_happen() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let (mut spectator, _) = connect_async(format!("ws://{addr}/rooms/{code}/spectate"))
+        .await
+        .unwrap();
+    expect(&mut spectator, "hello").await;
+
+    say(&mut host, json!({ "type": "start" })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+    expect_phase(&mut spectator, "solving").await;
+    let mut moved = make_a_move(&mut host).await;
+    let mut seen = expect(&mut spectator, "robot_moved").await;
+    // each connection numbers what it's sent on its own
+    moved["seq"] = Value::Null;
+    seen["seq"] = Value::Null;
+    assert_eq!(seen, moved);
+}
+
+#[tokio::test]
+async fn failing_a_solve_costs_points_down_to_the_floor() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": 7, "fail_penalty": 2, "points_floor": -1 }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    say(&mut host, json!({ "type": "start" })).await;
+
+    // a one move bid runs out after any move that misses
+    say(&mut host, json!({ "type": "bid", "moves": 1 })).await;
+    expect_phase(&mut host, "solving").await;
+    make_a_move(&mut host).await;
+    let leaderboard = expect(&mut host, "leaderboard").await;
+    assert_eq!(leaderboard["standings"][0]["points"], -1);
+
+    expect_phase(&mut host, "bidding").await;
+    say(&mut host, json!({ "type": "bid", "moves": 1 })).await;
+    expect_phase(&mut host, "solving").await;
+    make_a_move(&mut host).await;
+    // already at the floor, so nothing changes
+    let next = expect_any(&mut host, &["leaderboard", "round_over"]).await;
+    assert_eq!(next["type"], "round_over");
+}
+
+#[tokio::test]
+async fn undo_reverts_the_last_move() {
+    let (app, addr) = serve().await;
+    let mut host = solving(&app, addr).await;
+
+    let moved = make_a_move(&mut host).await;
+    say(&mut host, json!({ "type": "undo" })).await;
+    let undone = expect(&mut host, "move_undone").await;
+    assert_eq!(undone["robot"], "red");
+    assert_eq!(undone["from"], moved["to"]);
+    assert_eq!(undone["to"], moved["from"]);
+}
+
+#[tokio::test]
+async fn rounds_keep_a_history_of_their_attempts() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": 42, "rounds": 2, "board_size": 8 }),
+    )
+    .await;
+    let mut host = open(addr, &created["code"], &created["token"]).await;
+    let welcome = expect(&mut host, "welcome").await;
+    let mut board: Board = serde_json::from_value(welcome["board"].clone()).unwrap();
+
+    // a move that's then given up on
+    say(&mut host, json!({ "type": "start" })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+    expect_phase(&mut host, "solving").await;
+    let moved = make_a_move(&mut host).await;
+    say(&mut host, json!({ "type": "pass" })).await;
+
+    // then a round solved the shortest way
+    say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+    let solving = expect_phase(&mut host, "solving").await;
+    let target: Target = serde_json::from_value(solving["phase"]["target"].clone()).unwrap();
+    board.place_robots(serde_json::from_value(solving["phase"]["origin"].clone()).unwrap());
+    let path = board.solve(target).unwrap();
+    for (robot, direction) in &path {
+        say(
+            &mut host,
+            json!({ "type": "move", "robot": robot, "direction": direction }),
+        )
+        .await;
+    }
+    assert_eq!(expect(&mut host, "round_over").await["winner"], "host");
+    expect(&mut host, "game_over").await;
+
+    say(&mut host, json!({ "type": "request_history", "round": 1 })).await;
+    let history = expect(&mut host, "move_history").await;
+    assert_eq!(history["round"], 1);
+    let attempts = history["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 1);
+    assert_eq!(attempts[0]["solver"], "host");
+    assert_eq!(attempts[0]["solved"], false);
+    assert_eq!(attempts[0]["moves"][0]["to"], moved["to"]);
+
+    say(&mut host, json!({ "type": "request_history", "round": 2 })).await;
+    let history = expect(&mut host, "move_history").await;
+    assert_eq!(history["target"], solving["phase"]["target"]);
+    let attempts = history["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 1);
+    assert_eq!(attempts[0]["solved"], true);
+    assert_eq!(attempts[0]["moves"].as_array().unwrap().len(), path.len());
+
+    for round in [0, 3] {
+        say(
+            &mut host,
+            json!({ "type": "request_history", "round": round }),
+        )
+        .await;
+        assert_eq!(expect(&mut host, "error").await["code"], "round_not_found");
+    }
+}
+
+/// a room with the host solving round 1 on a target of the given kind, along with the board and
+/// the target.
+async fn solving_for(app: &Router, addr: SocketAddr, wildcard: bool) -> (Socket, Board, Target) {
+    for seed in 0.. {
+        let created = post_ok(
+            app,
+            "/rooms/create",
+            json!({ "username": "host", "seed": seed, "rounds": 1 }),
+        )
+        .await;
+        let mut host = open(addr, &created["code"], &created["token"]).await;
+        let board = expect(&mut host, "welcome").await["board"].clone();
+        say(&mut host, json!({ "type": "start" })).await;
+        let bidding = expect_phase(&mut host, "bidding").await;
+        let target: Target = serde_json::from_value(bidding["phase"]["target"].clone()).unwrap();
+        if (target.kind == TargetKind::Wildcard) != wildcard {
+            continue;
+        }
+        say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+        expect_phase(&mut host, "solving").await;
+        return (host, serde_json::from_value(board).unwrap(), target);
+    }
+    unreachable!()
+}
+
+#[tokio::test]
+async fn wildcards_are_won_by_any_robot() {
+    let (app, addr) = serve().await;
+    let (mut host, board, target) = solving_for(&app, addr, true).await;
+    assert_eq!(serde_json::to_value(target).unwrap()["robot"], Value::Null);
+
+    let path = board.solve(target).unwrap();
+    for (robot, direction) in &path {
+        say(
+            &mut host,
+            json!({ "type": "move", "robot": robot, "direction": direction }),
+        )
+        .await;
+    }
+    assert_eq!(expect(&mut host, "round_over").await["winner"], "host");
+}
+
+#[tokio::test]
+async fn colored_targets_need_their_own_robot() {
+    let (app, addr) = serve().await;
+    let (mut host, board, target) = solving_for(&app, addr, false).await;
+
+    // park some other robot on the target
+    let (robot, path) = Robot::ALL
+        .into_iter()
+        .filter(|&robot| !target.kind.accepts(robot))
+        .find_map(|robot| {
+            let decoy = Target {
+                kind: TargetKind::Robot(robot),
+                position: target.position,
+            };
+            board.solve(decoy).map(|path| (robot, path))
+        })
+        .unwrap();
+    for (robot, direction) in &path {
+        say(
+            &mut host,
+            json!({ "type": "move", "robot": robot, "direction": direction }),
+        )
+        .await;
+    }
+    say(&mut host, json!({ "type": "ping", "nonce": 1 })).await;
+    let seen = collect_until(&mut host, "pong").await;
+    let last = seen
+        .iter()
+        .rfind(|message| message["type"] == "robot_moved")
+        .unwrap();
+    assert_eq!(last["robot"], json!(robot));
+    assert_eq!(last["to"], json!(target.position));
+    assert!(!seen.iter().any(|message| message["type"] == "round_over"));
+}
+
+#[tokio::test]
+async fn undo_needs_a_move_to_undo() {
+    let (app, addr) = serve().await;
+    let mut host = solving(&app, addr).await;
+
+    say(&mut host, json!({ "type": "undo" })).await;
+    let error = expect(&mut host, "error").await;
+    assert_eq!(error["code"], "nothing_to_undo");
+}
+
+#[tokio::test]
+async fn only_the_solver_can_undo() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 30 })).await;
+    say(&mut robbie, json!({ "type": "bid", "moves": 40 })).await;
+    let solving = expect_phase(&mut robbie, "solving").await;
+    assert_eq!(solving["phase"]["solver"], "host");
+
+    make_a_move(&mut host).await;
+    say(&mut robbie, json!({ "type": "undo" })).await;
+    let error = expect(&mut robbie, "error").await;
+    assert_eq!(error["code"], "not_solver");
+}
+
+#[tokio::test]
+async fn passing_players_are_left_out_of_solving() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut robbie, json!({ "type": "bid", "moves": 3 })).await;
+    say(&mut robbie, json!({ "type": "pass" })).await;
+    let passed = expect(&mut host, "passed").await;
+    assert_eq!(passed["username"], "robbie");
+
+    say(&mut host, json!({ "type": "bid", "moves": 9 })).await;
+    let solving = expect_phase(&mut host, "solving").await;
+    assert_eq!(solving["phase"]["solver"], "host");
+    assert_eq!(solving["phase"]["queue"], json!([]));
+}
+
+#[tokio::test]
+async fn round_ends_when_everyone_passes() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut host, json!({ "type": "pass" })).await;
+    say(&mut robbie, json!({ "type": "pass" })).await;
+
+    let over = expect(&mut host, "round_over").await;
+    assert_eq!(over["round"], 1);
+    assert_eq!(over["winner"], Value::Null);
+}
+
+#[tokio::test]
+async fn kicked_players_are_told_why() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "kick", "username": "robbie" })).await;
+    assert_eq!(expect_close(&mut robbie).await, 4004);
+    assert_eq!(expect(&mut host, "leave").await["username"], "robbie");
+}
+
+#[tokio::test]
+async fn kicking_the_solver_moves_on_to_the_next() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut robbie, json!({ "type": "bid", "moves": 3 })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 9 })).await;
+    let solving = expect_phase(&mut host, "solving").await;
+    assert_eq!(solving["phase"]["solver"], "robbie");
+
+    say(&mut host, json!({ "type": "kick", "username": "robbie" })).await;
+    let solving = expect_phase(&mut host, "solving").await;
+    assert_eq!(solving["phase"]["solver"], "host");
+    assert_eq!(solving["phase"]["queue"], json!([]));
+}
+
+#[tokio::test]
+async fn kicked_bidders_are_left_out_of_solving() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut robbie, json!({ "type": "bid", "moves": 3 })).await;
+    expect(&mut host, "bid_placed").await;
+    say(&mut host, json!({ "type": "kick", "username": "robbie" })).await;
+    expect(&mut host, "leave").await;
+
+    say(&mut host, json!({ "type": "bid", "moves": 9 })).await;
+    let solving = expect_phase(&mut host, "solving").await;
+    assert_eq!(solving["phase"]["solver"], "host");
+    assert_eq!(solving["phase"]["queue"], json!([]));
+}
+
+#[tokio::test]
+async fn players_who_leave_are_told_why() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut robbie = connect(addr, &created["code"], &joined["token"]).await;
+
+    let (status, _) =
+        post_with_token(&app, &format!("/rooms/{code}/leave"), &joined["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(expect_close(&mut robbie).await, 4003);
+}
+
+#[tokio::test]
+async fn leaving_over_the_websocket_is_for_good() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+
+    // a dropped connection keeps robbie in the room
+    let robbie = connect(addr, &created["code"], &joined["token"]).await;
+    drop(robbie);
+    expect(&mut host, "disconnect").await;
+    let mut robbie = connect(addr, &created["code"], &joined["token"]).await;
+
+    say(&mut robbie, json!({ "type": "leave" })).await;
+    assert_eq!(expect_close(&mut robbie).await, 4003);
+    assert_eq!(expect(&mut host, "leave").await["username"], "robbie");
+
+    // and their token is gone with them
+    let mut request = format!("ws://{addr}/rooms/{code}/ws")
+        .into_client_request()
+        .unwrap();
+    let token = joined["token"].as_str().unwrap();
+    request
+        .headers_mut()
+        .insert("Authorization", format!("Bearer {token}").parse().unwrap());
+    assert!(connect_async(request).await.is_err());
+}
+
+#[tokio::test]
+async fn host_can_be_handed_over() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(
+        &mut host,
+        json!({ "type": "transfer_host", "to": "robbie" }),
+    )
+    .await;
+    assert_eq!(
+        expect(&mut host, "host_changed").await["username"],
+        "robbie"
+    );
+    assert_eq!(
+        expect(&mut robbie, "host_changed").await["username"],
+        "robbie"
+    );
+
+    say(&mut host, json!({ "type": "kick", "username": "robbie" })).await;
+    assert_eq!(expect(&mut host, "error").await["code"], "not_host");
+    say(&mut robbie, json!({ "type": "kick", "username": "host" })).await;
+    assert_eq!(expect(&mut robbie, "leave").await["username"], "host");
+}
+
+#[tokio::test]
+async fn host_can_only_be_handed_to_connected_players() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(
+        &mut robbie,
+        json!({ "type": "transfer_host", "to": "robbie" }),
+    )
+    .await;
+    assert_eq!(expect(&mut robbie, "error").await["code"], "not_host");
+
+    say(
+        &mut host,
+        json!({ "type": "transfer_host", "to": "roberta" }),
+    )
+    .await;
+    assert_eq!(expect(&mut host, "error").await["code"], "player_not_found");
+
+    drop(robbie);
+    expect(&mut host, "disconnect").await;
+    say(
+        &mut host,
+        json!({ "type": "transfer_host", "to": "robbie" }),
+    )
+    .await;
+    assert_eq!(
+        expect(&mut host, "error").await["code"],
+        "player_disconnected"
+    );
+}
+
+#[tokio::test]
+async fn full_rooms_start_once_everyone_is_ready() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "max_players": 2, "auto_start_when_full": true }),
+    )
+    .await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &joined["token"]).await;
+
+    say(&mut robbie, json!({ "type": "set_ready", "ready": true })).await;
+    assert_eq!(
+        expect_phase(&mut host, "bidding").await["phase"]["round"],
+        1
+    );
+}
+
+#[tokio::test]
+async fn auto_start_waits_for_the_room_to_fill() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "max_players": 3, "auto_start_when_full": true }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let robbie = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let roberta = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "roberta" }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let mut robbie = connect(addr, &created["code"], &robbie["token"]).await;
+
+    let (status, _) =
+        post_with_token(&app, &format!("/rooms/{code}/leave"), &roberta["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+    say(&mut robbie, json!({ "type": "set_ready", "ready": true })).await;
+    expect(&mut host, "ready_changed").await;
+
+    say(&mut host, json!({ "type": "ping", "nonce": 1 })).await;
+    let next = expect_any(&mut host, &["phase_changed", "pong"]).await;
+    assert_eq!(next["type"], "pong");
+}
+
+#[tokio::test]
+async fn players_reconnect_to_a_started_game_but_nobody_new_joins() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let mut robbie = connect(addr, &created["code"], &joined["token"]).await;
+
+    say(
+        &mut host,
+        json!({ "type": "adjust_points", "username": "robbie", "delta": 2 }),
+    )
+    .await;
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    assert_eq!(
+        expect(&mut robbie, "phase_changed").await["phase"]["name"],
+        "bidding"
+    );
+    say(&mut robbie, json!({ "type": "bid", "moves": 30 })).await;
+    expect(&mut robbie, "bid_placed").await;
+
+    drop(robbie);
+    expect(&mut host, "disconnect").await;
+
+    let mut robbie = open(addr, &created["code"], &joined["token"]).await;
+    let welcome = expect(&mut robbie, "welcome").await;
+    assert_eq!(welcome["phase"]["name"], "bidding");
+    assert_eq!(welcome["phase"]["bids"][0]["username"], "robbie");
+    let row = welcome["players"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|player| player["username"] == "robbie")
+        .unwrap();
+    assert_eq!(row["points"], 2);
+
+    let (status, body) = post_json(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "roberta" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "game_started");
+}
+
+#[tokio::test]
+async fn phase_lengths_are_announced() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "bid_seconds": 30, "solve_seconds": 45 }),
+    )
+    .await;
+    let code = &created["code"];
+    let mut host = open(addr, code, &created["token"]).await;
+    let welcome = expect(&mut host, "welcome").await;
+    assert_eq!(welcome["bid_seconds"], 30);
+    assert_eq!(welcome["solve_seconds"], 45);
+    assert_eq!(welcome["seconds_left"], Value::Null);
+
+    say(&mut host, json!({ "type": "start" })).await;
+    assert_eq!(expect_phase(&mut host, "bidding").await["seconds"], 30);
+    say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+    assert_eq!(expect_phase(&mut host, "solving").await["seconds"], 45);
+
+    // someone connecting partway through sees how long is left
+    say(&mut host, json!({ "type": "resync" })).await;
+    let welcome = expect(&mut host, "welcome").await;
+    assert!(welcome["seconds_left"].as_u64().unwrap() <= 45);
+}
+
+#[tokio::test]
+async fn a_finished_game_can_be_reset_for_a_rematch() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "rounds": 1 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = open(addr, &created["code"], &created["token"]).await;
+    let board = expect(&mut host, "welcome").await["board"].clone();
+    let reset = format!("/rooms/{code}/reset?same_seed=true");
+
+    let (status, body) = post_with_token(&app, &reset, &created["token"]).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "game_not_over");
+
+    say(
+        &mut host,
+        json!({ "type": "adjust_points", "username": "robbie", "delta": 3 }),
+    )
+    .await;
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    say(&mut host, json!({ "type": "pass" })).await;
+    expect(&mut host, "game_over").await;
+
+    let (status, body) = post_with_token(&app, &reset, &joined["token"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "not_host");
+
+    let (status, _) = post_with_token(&app, &reset, &created["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+    let reset = expect(&mut host, "game_reset").await;
+    assert_eq!(reset["board"], board);
+    let players = reset["players"].as_array().unwrap();
+    assert_eq!(players.len(), 2);
+    assert!(players.iter().all(|player| player["points"] == 0));
+
+    let (_, summary) = get(&app, &format!("/rooms/{code}")).await;
+    assert_eq!(summary["started"], false);
+    // robbie's token still works, and the room can start over
+    let (status, _) =
+        post_with_token(&app, &format!("/rooms/{code}/leave"), &joined["token"]).await;
+    assert_eq!(status, StatusCode::OK);
+    say(&mut host, json!({ "type": "start" })).await;
+    expect_phase(&mut host, "bidding").await;
+}
+
+#[tokio::test]
+async fn hidden_scores_are_only_revealed_at_the_end() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "rounds": 1, "hide_scores_until_end": true }),
+    )
+    .await;
+    let mut host = open_with_query(
+        addr,
+        &created["code"],
+        &created["token"],
+        "player_updates=true",
+    )
+    .await;
+    let welcome = expect(&mut host, "welcome").await;
+    assert!(welcome["players"][0].get("points").is_none());
+
+    say(
+        &mut host,
+        json!({ "type": "adjust_points", "username": "host", "delta": 2 }),
+    )
+    .await;
+    say(&mut host, json!({ "type": "ping", "nonce": 1 })).await;
+    let mut updated = false;
+    loop {
+        let message = expect_any(&mut host, &["player_update", "leaderboard", "pong"]).await;
+        match message["type"].as_str().unwrap() {
+            "player_update" => {
+                assert!(message["descriptor"].get("points").is_none());
+                updated = true;
+            }
+            "leaderboard" => panic!("leaderboard sent while scores are hidden"),
+            _ => break,
+        }
+    }
+    assert!(updated);
+
+    say(&mut host, json!({ "type": "start" })).await;
+    say(&mut host, json!({ "type": "pass" })).await;
+    let over = expect(&mut host, "game_over").await;
+    assert_eq!(over["standings"][0]["points"], 2);
+}
+
+#[tokio::test]
+async fn a_game_nobody_is_connected_to_plays_itself_out() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "rounds": 1 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let (mut spectator, _) = connect_async(format!("ws://{addr}/rooms/{code}/spectate"))
+        .await
+        .unwrap();
+
+    say(&mut host, json!({ "type": "start" })).await;
+    expect_phase(&mut host, "bidding").await;
+    drop(host);
+
+    // rather than a minute of bidding, the round is given up within a couple of ticks
+    let over = expect(&mut spectator, "round_over").await;
+    assert_eq!(over["winner"], Value::Null);
+    expect(&mut spectator, "game_over").await;
+}
+
+#[tokio::test]
+async fn the_seed_is_only_given_away_once_the_game_is_over() {
+    let (app, addr) = serve().await;
+    let created = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "rounds": 1 }),
+    )
+    .await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = open(addr, &created["code"], &created["token"]).await;
+    let welcome = expect(&mut host, "welcome").await;
+
+    say(&mut host, json!({ "type": "start" })).await;
+    say(&mut host, json!({ "type": "bid", "moves": 40 })).await;
+    expect_phase(&mut host, "solving").await;
+    say(&mut host, json!({ "type": "pass" })).await;
+    let mut messages = collect_until(&mut host, "game_over").await;
+    let over = messages.pop().unwrap();
+    for message in messages.iter().chain([&welcome]) {
+        assert!(!message.to_string().contains("seed"), "{message}");
+    }
+
+    let seed = over["seed"].as_u64().unwrap();
+    let (_, results) = get(&app, &format!("/rooms/{code}/results")).await;
+    assert_eq!(results["seed"], seed);
+
+    // and it deals the same board again
+    let replay = post_ok(
+        &app,
+        "/rooms/create",
+        json!({ "username": "host", "seed": seed }),
+    )
+    .await;
+    let mut host = open(addr, &replay["code"], &replay["token"]).await;
+    assert_eq!(
+        expect(&mut host, "welcome").await["board"],
+        welcome["board"]
+    );
+}
+
+#[tokio::test]
+async fn the_host_hands_out_robots() {
+    let (app, addr) = serve().await;
+    let (mut host, mut robbie) = lobby(&app, addr).await;
+
+    say(
+        &mut robbie,
+        json!({ "type": "assign_robot", "username": "robbie", "robot": "blue" }),
+    )
+    .await;
+    assert_eq!(expect(&mut robbie, "error").await["code"], "not_host");
+
+    say(
+        &mut host,
+        json!({ "type": "assign_robot", "username": "robbie", "robot": "blue" }),
+    )
+    .await;
+    let assigned = expect(&mut robbie, "robot_assigned").await;
+    assert_eq!(assigned["robot"], "blue");
+    assert_eq!(assigned["username"], "robbie");
+
+    // a robot only has one owner at a time
+    for (username, robot) in [("host", "blue"), ("host", "red"), ("robbie", "green")] {
+        say(
+            &mut host,
+            json!({ "type": "assign_robot", "username": username, "robot": robot }),
+        )
+        .await;
+        expect(&mut host, "robot_assigned").await;
+    }
+    say(
+        &mut host,
+        json!({ "type": "assign_robot", "username": null, "robot": "red" }),
+    )
+    .await;
+    expect(&mut host, "robot_assigned").await;
+
+    say(&mut robbie, json!({ "type": "resync" })).await;
+    let welcome = expect(&mut robbie, "welcome").await;
+    let robots = |username: &str| {
+        welcome["players"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|player| player["username"] == username)
+            .unwrap()["robots"]
+            .clone()
+    };
+    assert_eq!(robots("host"), json!(["blue"]));
+    assert_eq!(robots("robbie"), json!(["green"]));
+}
+
+#[tokio::test]
+async fn players_only_move_the_robots_they_were_given() {
+    let (app, addr) = serve().await;
+    let mut host = solving(&app, addr).await;
+
+    say(
+        &mut host,
+        json!({ "type": "assign_robot", "username": "host", "robot": "red" }),
+    )
+    .await;
+    expect(&mut host, "robot_assigned").await;
+
+    for direction in ["up", "down", "left", "right"] {
+        say(
+            &mut host,
+            json!({ "type": "move", "robot": "blue", "direction": direction }),
+        )
+        .await;
+        let rejected = expect(&mut host, "move_rejected").await;
+        assert_eq!(rejected["reason"], "not_your_robot");
+    }
+    assert_eq!(make_a_move(&mut host).await["robot"], "red");
+}
+
+#[tokio::test]
+async fn the_host_can_change_settings_until_the_game_starts() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    let uri = format!("/rooms/{code}/settings");
+
+    let settings = json!({ "rounds": 3, "bid_seconds": 30, "board_size": 8 });
+    let (status, _) = post_json_with_token(&app, &uri, &created["token"], settings).await;
+    assert_eq!(status, StatusCode::OK);
+    let changed = expect(&mut host, "settings_changed").await;
+    assert_eq!(changed["settings"]["rounds"], 3);
+    assert_eq!(changed["settings"]["bid_seconds"], 30);
+    assert_eq!(changed["board"]["size"], 8);
+    say(&mut host, json!({ "type": "resync" })).await;
+    let welcome = expect(&mut host, "welcome").await;
+    assert_eq!(welcome["rounds"], 3);
+    assert_eq!(welcome["board"]["size"], 8);
+
+    let (status, body) =
+        post_json_with_token(&app, &uri, &joined["token"], json!({ "rounds": 1 })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "not_host");
+
+    let (status, body) =
+        post_json_with_token(&app, &uri, &created["token"], json!({ "solve_seconds": 0 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_setting");
+
+    // too few seats for everyone already in
+    post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "roberta" }),
+    )
+    .await;
+    let (status, body) =
+        post_json_with_token(&app, &uri, &created["token"], json!({ "max_players": 2 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_setting");
+
+    say(&mut host, json!({ "type": "start", "force": true })).await;
+    expect_phase(&mut host, "bidding").await;
+    let (status, body) =
+        post_json_with_token(&app, &uri, &created["token"], json!({ "rounds": 1 })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "game_started");
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use common::{connect, expect, post_ok, say, serve};
+use serde_json::json;
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id},
+};
+use tracing_subscriber::{
+    layer::{Context, Layer, SubscriberExt},
+    registry::{LookupSpan, Registry},
+};
+
+/// an event's message, with the name and fields of every span it happened in, innermost first.
+type Logged = (String, Vec<(&'static str, BTreeMap<String, String>)>);
+
+type Events = Arc<Mutex<Vec<Logged>>>;
+
+/// keeps every event along with the spans around it.
+struct Capture(Events);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Capture {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        ctx.span(id).unwrap().extensions_mut().insert(fields);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let spans = ctx
+            .event_scope(event)
+            .into_iter()
+            .flatten()
+            .map(|span| {
+                let fields = span.extensions().get::<Fields>().unwrap().0.clone();
+                (span.name(), fields)
+            })
+            .collect();
+        let message = fields.0.remove("message").unwrap_or_default();
+        self.0.lock().unwrap().push((message, spans));
+    }
+}
+
+#[derive(Default)]
+struct Fields(BTreeMap<String, String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().into(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+}
+
+/// the spans around the first event whose message starts with `message`.
+fn spans_around(events: &Events, message: &str) -> Vec<(&'static str, BTreeMap<String, String>)> {
+    let events = events.lock().unwrap();
+    let (_, spans) = events
+        .iter()
+        .find(|(logged, _)| logged.starts_with(message))
+        .unwrap_or_else(|| panic!("nothing logged {message:?}"));
+    spans.clone()
+}
+
+#[tokio::test]
+async fn room_logs_carry_the_code_and_player() {
+    let events = Events::default();
+    // the test runtime is single threaded, so this sees the server's events too
+    let _guard =
+        tracing::subscriber::set_default(Registry::default().with(Capture(events.clone())));
+
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = created["code"].as_str().unwrap();
+    let mut host = connect(addr, &created["code"], &created["token"]).await;
+    say(&mut host, json!({ "type": "chat", "text": "hi" })).await;
+    expect(&mut host, "chat").await;
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{code}/join"),
+        json!({ "username": "guest" }),
+    )
+    .await;
+    assert_eq!(joined["username"], "guest");
+
+    let connecting = spans_around(&events, "player host connecting");
+    assert_eq!(connecting[0].0, "ws");
+    assert_eq!(connecting[0].1["code"], code);
+    assert_eq!(connecting[0].1["user"], "host");
+
+    let chatting = spans_around(&events, "sending message Chat");
+    assert_eq!(chatting[0].0, "room");
+    assert_eq!(chatting[0].1["code"], code);
+    assert_eq!(chatting[0].1["user"], "host");
+    // and still inside the connection it came in on
+    assert!(chatting.iter().any(|(name, _)| *name == "ws"));
+
+    let joining = spans_around(&events, "sending message Join");
+    assert_eq!(joining[0].0, "room");
+    assert_eq!(joining[0].1["code"], code);
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use std::{net::SocketAddr, path::Path, sync::Arc};
+
+use axum::serve::ListenerExt;
+use rusty_robots::tls::{self, TlsError, TlsListener};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::{
+    TlsConnector,
+    client::TlsStream,
+    rustls::{
+        ClientConfig, RootCertStore,
+        pki_types::{CertificateDer, ServerName, pem::PemObject},
+    },
+};
+
+const CERT: &str = "tests/fixtures/cert.pem";
+const KEY: &str = "tests/fixtures/key.pem";
+
+/// serves the api over tls with the self-signed fixture.
+async fn serve_tls() -> SocketAddr {
+    let acceptor = tls::acceptor(Path::new(CERT), Path::new(KEY)).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = TlsListener::new(listener, acceptor).tap_io(|_| ());
+    tokio::spawn(axum::serve(listener, common::app()).into_future());
+    addr
+}
+
+/// connects to `addr` trusting only the fixture certificate.
+async fn connect_tls(addr: SocketAddr) -> TlsStream<TcpStream> {
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_file(CERT).unwrap())
+        .unwrap();
+    let connector = TlsConnector::from(Arc::new(
+        ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    ));
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    connector
+        .connect(ServerName::try_from("localhost").unwrap(), tcp)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn serves_the_api_over_https() {
+    let addr = serve_tls().await;
+    let mut stream = connect_tls(addr).await;
+    stream
+        .write_all(b"GET /rooms HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+}
+
+#[tokio::test]
+async fn a_failed_handshake_does_not_stop_the_listener() {
+    let addr = serve_tls().await;
+
+    // plain http at a tls port is refused at the handshake
+    let mut plain = TcpStream::connect(addr).await.unwrap();
+    plain
+        .write_all(b"GET /rooms HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut rest = Vec::new();
+    let _ = plain.read_to_end(&mut rest).await;
+    assert!(!rest.starts_with(b"HTTP/1.1"));
+
+    connect_tls(addr).await;
+}
+
+#[test]
+fn rejects_files_without_certificates() {
+    assert!(matches!(
+        tls::acceptor(Path::new(KEY), Path::new(KEY)),
+        Err(TlsError::NoCertificates(_))
+    ));
+    assert!(matches!(
+        tls::acceptor(Path::new("tests/fixtures/missing.pem"), Path::new(KEY)),
+        Err(TlsError::Pem { .. })
+    ));
+}
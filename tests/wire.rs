@@ -0,0 +1,248 @@
+mod common;
+
+use std::{io::Read, time::Duration};
+
+use flate2::read::DeflateDecoder;
+use futures_util::{SinkExt, StreamExt};
+use rusty_robots::config::ServerConfig;
+use serde_json::{Value, json};
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::tungstenite::{
+    Message,
+    protocol::frame::{
+        Frame,
+        coding::{Data, OpCode},
+    },
+};
+
+use common::*;
+
+/// the next data frame, skipping pings and the like.
+async fn next_frame(socket: &mut Socket) -> Message {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                message @ (Message::Text(_) | Message::Binary(_)) => return message,
+                _ => continue,
+            }
+        }
+    })
+    .await
+    .expect("no message")
+}
+
+fn inflate(bytes: &[u8]) -> Value {
+    let mut json = String::new();
+    DeflateDecoder::new(bytes)
+        .read_to_string(&mut json)
+        .unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+#[tokio::test]
+async fn large_messages_are_deflated_when_asked() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+
+    let mut socket = open_with_query(
+        addr,
+        &created["code"],
+        &created["token"],
+        "compress=deflate",
+    )
+    .await;
+    // too small to be worth compressing
+    let Message::Text(hello) = next_frame(&mut socket).await else {
+        panic!("hello was compressed");
+    };
+    assert!(hello.contains("\"hello\""));
+    let Message::Binary(compressed) = next_frame(&mut socket).await else {
+        panic!("welcome wasn't compressed");
+    };
+    drop(socket);
+    sleep(Duration::from_millis(100)).await;
+
+    let mut socket = open(addr, &created["code"], &created["token"]).await;
+    next_frame(&mut socket).await;
+    let Message::Text(plain) = next_frame(&mut socket).await else {
+        panic!("welcome was compressed without being asked");
+    };
+
+    assert!(compressed.len() < plain.len());
+    // the same welcome, numbered the same as the first message of each connection
+    let inflated = inflate(&compressed);
+    assert_eq!(inflated["seq"], 0);
+    assert_eq!(inflated, serde_json::from_str::<Value>(&plain).unwrap());
+}
+
+#[tokio::test]
+async fn compression_can_be_turned_off() {
+    let (app, addr) = serve_with(ServerConfig {
+        compression: false,
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+
+    let mut socket = open_with_query(
+        addr,
+        &created["code"],
+        &created["token"],
+        "compress=deflate",
+    )
+    .await;
+    next_frame(&mut socket).await;
+    let Message::Text(welcome) = next_frame(&mut socket).await else {
+        panic!("welcome was compressed");
+    };
+    assert!(welcome.contains("\"welcome\""));
+}
+
+async fn next_msgpack(socket: &mut Socket) -> Value {
+    let Message::Binary(bytes) = next_frame(socket).await else {
+        panic!("msgpack sent as text");
+    };
+    rmp_serde::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn msgpack_goes_both_ways() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+
+    let mut socket = open_with_query(
+        addr,
+        &created["code"],
+        &created["token"],
+        "format=msgpack&compress=deflate",
+    )
+    .await;
+    assert_eq!(next_msgpack(&mut socket).await["type"], "hello");
+    // large, but not deflated since msgpack ignores compression
+    assert_eq!(next_msgpack(&mut socket).await["type"], "welcome");
+
+    let ping = rmp_serde::to_vec_named(&json!({ "type": "ping", "nonce": 7 })).unwrap();
+    socket.send(Message::binary(ping)).await.unwrap();
+    let mut pong = next_msgpack(&mut socket).await;
+    while pong["type"] != "pong" {
+        pong = next_msgpack(&mut socket).await;
+    }
+    assert_eq!(pong["nonce"], 7);
+}
+
+/// every message up to and including the next `pong`.
+async fn until_pong(socket: &mut Socket) -> Vec<Value> {
+    let mut messages = Vec::new();
+    loop {
+        let Message::Text(text) = next_frame(socket).await else {
+            continue;
+        };
+        let message: Value = serde_json::from_str(&text).unwrap();
+        let done = message["type"] == "pong";
+        messages.push(message);
+        if done {
+            return messages;
+        }
+    }
+}
+
+/// the numbers of `messages`, failing unless each follows on from the last.
+fn assert_numbered_from(first: u64, messages: &[Value]) {
+    let seqs = messages
+        .iter()
+        .map(|message| message["seq"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    let expected = (first..).take(seqs.len()).collect::<Vec<_>>();
+    assert_eq!(seqs, expected);
+}
+
+#[tokio::test]
+async fn each_connection_numbers_its_messages_without_gaps() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let code = &created["code"];
+    let joined = post_ok(
+        &app,
+        &format!("/rooms/{}/join", code.as_str().unwrap()),
+        json!({ "username": "robbie" }),
+    )
+    .await;
+    let mut host = connect(addr, code, &created["token"]).await;
+    let mut robbie = connect(addr, code, &joined["token"]).await;
+
+    // messages meant only for one of them don't leave a gap for the other
+    say(&mut robbie, json!({ "type": "ping", "nonce": 1 })).await;
+    let robbies = until_pong(&mut robbie).await;
+    for kind in ["laugh", "clap", "wow"] {
+        say(&mut host, json!({ "type": "emote", "kind": kind })).await;
+    }
+    say(
+        &mut host,
+        json!({ "type": "whisper", "to": "robbie", "text": "psst" }),
+    )
+    .await;
+    say(&mut host, json!({ "type": "ping", "nonce": 2 })).await;
+    let hosts = until_pong(&mut host).await;
+    say(&mut robbie, json!({ "type": "ping", "nonce": 3 })).await;
+    let robbies = [robbies, until_pong(&mut robbie).await].concat();
+
+    // the welcome was each one's first
+    assert_numbered_from(1, &hosts);
+    assert_numbered_from(1, &robbies);
+    assert!(robbies.iter().any(|message| message["type"] == "whisper"));
+    assert_eq!(
+        robbies
+            .iter()
+            .filter(|message| message["type"] == "emote")
+            .count(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn oversize_messages_close_the_socket() {
+    let (app, addr) = serve_with(ServerConfig {
+        max_message_bytes: 1024,
+        ..ServerConfig::default()
+    })
+    .await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let (code, token) = (&created["code"], &created["token"]);
+
+    let mut socket = connect(addr, code, token).await;
+    let nonce = "9".repeat(2048);
+    socket
+        .send(Message::text(format!(
+            r#"{{"type":"ping","nonce":{nonce}}}"#
+        )))
+        .await
+        .unwrap();
+    assert_eq!(expect_close(&mut socket).await, 1008);
+
+    // the connection was let go, so the player can come straight back
+    let mut socket = connect(addr, code, token).await;
+    say(&mut socket, json!({ "type": "ping", "nonce": 1 })).await;
+    expect(&mut socket, "pong").await;
+}
+
+#[tokio::test]
+async fn broken_frames_close_the_socket_without_details() {
+    let (app, addr) = serve().await;
+    let created = post_ok(&app, "/rooms/create", json!({ "username": "host" })).await;
+    let mut socket = connect(addr, &created["code"], &created["token"]).await;
+
+    // text that isn't utf-8
+    let frame = Frame::message(vec![0xff, 0xfe], OpCode::Data(Data::Text), true);
+    socket.send(Message::Frame(frame)).await.unwrap();
+    let frame = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Message::Close(frame) = socket.next().await.unwrap().unwrap() {
+                return frame.unwrap();
+            }
+        }
+    })
+    .await
+    .expect("socket not closed");
+    assert_eq!(u16::from(frame.code), 1011);
+    assert_eq!(frame.reason, "connection error");
+}